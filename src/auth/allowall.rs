@@ -0,0 +1,26 @@
+use eternalreckoning_core::net::operation::Credentials;
+
+use super::{
+    AuthError,
+    Authenticator,
+    Identity,
+};
+
+pub struct AllowAll;
+
+impl Authenticator for AllowAll {
+    fn authenticate(&self, credentials: &Credentials)
+        -> Result<Identity, AuthError>
+    {
+        let name = match credentials.username.trim() {
+            "" => "anonymous".to_string(),
+            username => username.to_string(),
+        };
+
+        Ok(Identity { account: None, name })
+    }
+
+    fn has_accounts(&self) -> bool {
+        false
+    }
+}
\ No newline at end of file
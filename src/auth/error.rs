@@ -0,0 +1,11 @@
+use failure_derive::Fail;
+
+#[derive(Debug, Fail)]
+pub enum AuthError {
+    #[fail(display = "Unknown user: {}", _0)]
+    UnknownUser(String),
+    #[fail(display = "Invalid password for user: {}", _0)]
+    InvalidPassword(String),
+    #[fail(display = "Malformed password hash for user: {}", _0)]
+    MalformedHash(String),
+}
\ No newline at end of file
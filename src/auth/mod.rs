@@ -0,0 +1,73 @@
+mod allowall;
+mod error;
+mod staticfile;
+mod worker;
+
+use failure::Error;
+use serde::{Serialize, Deserialize};
+
+use eternalreckoning_core::net::operation::Credentials;
+
+pub use allowall::AllowAll;
+pub use error::AuthError;
+pub use staticfile::{
+    hash_password,
+    StaticFile,
+    DEFAULT_ITERATIONS,
+};
+pub use worker::AuthWorker;
+
+pub struct Identity {
+    /// Stable key for the player's saved state, set only when the
+    /// credentials were actually verified.
+    pub account: Option<String>,
+    pub name: String,
+}
+
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, credentials: &Credentials)
+        -> Result<Identity, AuthError>;
+
+    /// Whether identities carry an account. Without one there is no player
+    /// state to save.
+    fn has_accounts(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthBackend {
+    AllowAll,
+    StaticFile,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AuthConfig {
+    pub backend: AuthBackend,
+    pub users_file: String,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            backend: AuthBackend::AllowAll,
+            users_file: "config/users.toml".to_string(),
+        }
+    }
+}
+
+pub fn from_config(config: &AuthConfig)
+    -> Result<Box<dyn Authenticator>, Error>
+{
+    match config.backend {
+        AuthBackend::AllowAll => {
+            log::warn!("Authentication disabled, accepting all clients");
+            Ok(Box::new(AllowAll))
+        },
+        AuthBackend::StaticFile => {
+            Ok(Box::new(StaticFile::from_file(&config.users_file)?))
+        },
+    }
+}
\ No newline at end of file
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs;
+
+use failure::{
+    format_err,
+    Error,
+};
+use hmac::{
+    Hmac,
+    Mac,
+};
+use serde::Deserialize;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use eternalreckoning_core::net::operation::Credentials;
+
+use super::{
+    AuthError,
+    Authenticator,
+    Identity,
+};
+
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+const SALT_LENGTH: usize = 16;
+const DIGEST_LENGTH: usize = 32;
+
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+#[derive(Deserialize)]
+struct UsersFile {
+    #[serde(default)]
+    users: HashMap<String, String>,
+}
+
+pub struct StaticFile {
+    users: HashMap<String, String>,
+}
+
+impl StaticFile {
+    pub fn from_file(path: &str) -> Result<StaticFile, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| {
+                format_err!("Failed to read users file {}: {}", path, err)
+            })?;
+        let file: UsersFile = toml::from_str(&contents)
+            .map_err(|err| {
+                format_err!("Failed to parse users file {}: {}", path, err)
+            })?;
+
+        log::info!("Loaded {} users from {}", file.users.len(), path);
+
+        Ok(StaticFile { users: file.users })
+    }
+}
+
+impl Authenticator for StaticFile {
+    fn authenticate(&self, credentials: &Credentials)
+        -> Result<Identity, AuthError>
+    {
+        let username = &credentials.username;
+        let hash = self.users.get(username)
+            .ok_or_else(|| AuthError::UnknownUser(username.clone()))?;
+
+        let malformed = || AuthError::MalformedHash(username.clone());
+
+        let mut parts = hash.splitn(4, ':');
+        let (iterations, salt, digest) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(HASH_SCHEME), Some(iterations), Some(salt), Some(digest)) => {
+                    (iterations, salt, digest)
+                },
+                _ => return Err(malformed()),
+            };
+        let iterations = iterations.parse::<u32>()
+            .ok()
+            .filter(|iterations| *iterations > 0)
+            .ok_or_else(malformed)?;
+        let salt = hex::decode(salt).map_err(|_| malformed())?;
+        let digest = hex::decode(digest).map_err(|_| malformed())?;
+        if digest.is_empty() {
+            return Err(malformed());
+        }
+
+        let mut derived = vec![0; digest.len()];
+        pbkdf2(credentials.password.as_bytes(), &salt, iterations, &mut derived);
+
+        if !bool::from(derived.ct_eq(&digest)) {
+            return Err(AuthError::InvalidPassword(username.clone()));
+        }
+
+        Ok(Identity {
+            account: Some(username.clone()),
+            name: username.clone(),
+        })
+    }
+}
+
+pub fn hash_password(password: &str, iterations: u32) -> String {
+    let salt: [u8; SALT_LENGTH] = rand::random();
+
+    let mut digest = [0; DIGEST_LENGTH];
+    pbkdf2(password.as_bytes(), &salt, iterations, &mut digest);
+
+    format!(
+        "{}:{}:{}:{}",
+        HASH_SCHEME,
+        iterations,
+        hex::encode(salt),
+        hex::encode(digest)
+    )
+}
+
+/// PBKDF2 with HMAC-SHA256 as the pseudorandom function (RFC 8018).
+fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    let prf = Hmac::<Sha256>::new_varkey(password)
+        .expect("HMAC accepts keys of any length");
+
+    for (index, block) in output.chunks_mut(DIGEST_LENGTH).enumerate() {
+        let mut mac = prf.clone();
+        mac.input(salt);
+        mac.input(&(index as u32 + 1).to_be_bytes());
+        let mut u = mac.result().code();
+
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut mac = prf.clone();
+            mac.input(&u);
+            u = mac.result().code();
+
+            for (t, u) in t.iter_mut().zip(u.iter()) {
+                *t ^= u;
+            }
+        }
+
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(password: &str) -> Credentials {
+        Credentials {
+            username: "player".to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    fn users(hash: String) -> StaticFile {
+        let mut users = HashMap::new();
+        users.insert("player".to_string(), hash);
+        StaticFile { users }
+    }
+
+    #[test]
+    fn pbkdf2_matches_reference_vector() {
+        // RFC 7914, section 11
+        let mut output = [0; 64];
+        pbkdf2(b"passwd", b"salt", 1, &mut output);
+
+        assert_eq!(
+            hex::encode(&output[..]),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+    }
+
+    #[test]
+    fn accepts_only_the_hashed_password() {
+        let file = users(hash_password("secret", 10));
+
+        assert!(file.authenticate(&credentials("secret")).is_ok());
+        match file.authenticate(&credentials("secrets")) {
+            Err(AuthError::InvalidPassword(_)) => (),
+            _ => panic!("wrong password accepted"),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        let hashes = [
+            "sha256:00:00",
+            "pbkdf2-sha256:0:00:00",
+            "pbkdf2-sha256:10:00:",
+            "pbkdf2-sha256:10:zz:00",
+        ];
+
+        for hash in hashes.iter() {
+            match users(hash.to_string()).authenticate(&credentials("secret")) {
+                Err(AuthError::MalformedHash(_)) => (),
+                _ => panic!("accepted malformed hash {}", hash),
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    SyncSender,
+    TrySendError,
+};
+use std::thread;
+
+use failure::{
+    format_err,
+    Error,
+};
+use uuid::Uuid;
+
+use eternalreckoning_core::net::operation::Credentials;
+
+use super::{
+    AuthError,
+    Authenticator,
+    Identity,
+};
+
+pub type AuthResult = (Uuid, Result<Identity, AuthError>);
+
+/// Requests waiting for the thread. Further ones are turned away.
+const MAX_PENDING: usize = 256;
+
+/// Checks credentials on a thread of its own, since password hashing is
+/// deliberately slow. The thread exits once the worker is dropped.
+pub struct AuthWorker {
+    requests: SyncSender<(Uuid, Credentials)>,
+    results: Receiver<AuthResult>,
+}
+
+impl AuthWorker {
+    pub fn spawn(authenticator: Box<dyn Authenticator>) -> Result<AuthWorker, Error> {
+        let (requests, request_rx) = mpsc::sync_channel::<(Uuid, Credentials)>(MAX_PENDING);
+        let (result_tx, results) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("authentication".to_string())
+            .spawn(move || {
+                for (uuid, credentials) in request_rx {
+                    let result = authenticator.authenticate(&credentials);
+                    if result_tx.send((uuid, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|err| {
+                format_err!("Failed to start authentication thread: {}", err)
+            })?;
+
+        Ok(AuthWorker { requests, results })
+    }
+
+    /// Queues credentials to be checked. Returns false when too many are
+    /// waiting already.
+    pub fn submit(&self, uuid: Uuid, credentials: Credentials) -> Result<bool, Error> {
+        match self.requests.try_send((uuid, credentials)) {
+            Ok(()) => Ok(true),
+            Err(TrySendError::Full(_)) => Ok(false),
+            Err(TrySendError::Disconnected(_)) => {
+                Err(format_err!("Authentication thread stopped"))
+            },
+        }
+    }
+
+    /// Returns a finished check, if any. Failures to reach the thread are
+    /// reported by `submit`.
+    pub fn try_recv(&self) -> Option<AuthResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Accepts everyone, once allowed to go on.
+    struct Gate(Mutex<Receiver<()>>);
+
+    impl Authenticator for Gate {
+        fn authenticate(&self, credentials: &Credentials)
+            -> Result<Identity, AuthError>
+        {
+            self.0.lock().unwrap().recv().ok();
+            Ok(Identity { account: None, name: credentials.username.clone() })
+        }
+    }
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "player".to_string(),
+            password: String::new(),
+        }
+    }
+
+    #[test]
+    fn turns_requests_away_while_the_queue_is_full() {
+        let (open, gate) = mpsc::channel();
+        let worker = AuthWorker::spawn(Box::new(Gate(Mutex::new(gate)))).unwrap();
+
+        // One request is taken by the thread, the rest wait in the queue.
+        let mut submitted = 0;
+        while worker.submit(Uuid::new_v4(), credentials()).unwrap() {
+            submitted += 1;
+            assert!(submitted <= MAX_PENDING + 1);
+        }
+        assert!(submitted >= MAX_PENDING);
+
+        drop(open);
+        let mut finished = 0;
+        while finished < submitted {
+            if worker.try_recv().is_some() {
+                finished += 1;
+            }
+        }
+        assert!(worker.submit(Uuid::new_v4(), credentials()).unwrap());
+    }
+}
@@ -1,3 +1,10 @@
+use std::io;
+
+use bytes::BytesMut;
+use tokio::codec::{
+    Decoder,
+    Encoder,
+};
 use tokio::net::{
     UdpSocket,
     UdpFramed,
@@ -11,6 +18,59 @@ use eternalreckoning_core::net::{
         Operation,
     },
 };
+use eternalreckoning_server::networking::{
+    Header,
+    Packet,
+    PacketCodec,
+};
+
+fn packet(sequence: u16, op: Operation) -> Packet {
+    let mut payload = BytesMut::new();
+    EternalReckoningCodec.encode(op, &mut payload).unwrap();
+
+    Packet {
+        header: Header {
+            sequence,
+            ack: None,
+            reliable_id: None,
+        },
+        payload: Some(payload.freeze()),
+    }
+}
+
+fn connect(cookie: Option<u64>) -> Packet {
+    packet(
+        cookie.map_or(0, |_| 1),
+        Operation::ClConnectMessage(operation::ClConnectMessage {
+            cookie,
+            session: None,
+            credentials: operation::Credentials {
+                username: "ft-connect".to_string(),
+                password: String::new(),
+            },
+        })
+    )
+}
+
+fn receive(framed: UdpFramed<PacketCodec>)
+    -> impl Future<Item = (Operation, UdpFramed<PacketCodec>), Error = io::Error>
+{
+    framed
+        .filter(|(packet, _addr)| packet.payload.is_some())
+        .into_future()
+        .map_err(|(err, _stream)| err)
+        .map(|(packet, stream)| {
+            assert!(packet.is_some());
+
+            let (packet, _addr) = packet.unwrap();
+            let mut payload = BytesMut::from(packet.payload.unwrap());
+            let op = EternalReckoningCodec.decode(&mut payload)
+                .unwrap()
+                .unwrap();
+
+            (op, stream.into_inner())
+        })
+}
 
 fn main() {
     let addr = ([127, 0, 0, 1], 6142).into();
@@ -20,20 +80,23 @@ fn main() {
 
     let stream = UdpFramed::new(
         socket,
-        EternalReckoningCodec
+        PacketCodec
     );
 
     let sequence = stream
-        .send((Operation::ClConnectMessage(operation::ClConnectMessage), addr))
-        .and_then(|framed| {
-            framed.into_future().map_err(|(err, _stream)| err)
-        })
-        .map(|(op, _stream)| {
-            assert!(op.is_some());
-
-            let op = op.unwrap();
+        .send((connect(None), addr))
+        .and_then(receive)
+        .and_then(move |(op, framed)| {
+            let cookie = match op {
+                Operation::SvConnectChallenge(challenge) => challenge.cookie,
+                _ => panic!("Expected a connection challenge"),
+            };
 
-            if let Operation::SvConnectResponse(operation::SvConnectResponse { uuid }) = op.0 {
+            framed.send((connect(Some(cookie)), addr))
+        })
+        .and_then(receive)
+        .map(|(op, _framed)| {
+            if let Operation::SvConnectResponse(operation::SvConnectResponse { uuid, .. }) = op {
                 println!("Connected with UUID {}", uuid);
                 eprintln!("Result: OK");
             } else {
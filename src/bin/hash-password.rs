@@ -0,0 +1,134 @@
+use std::env;
+use std::io::{
+    self,
+    BufRead,
+    Write,
+};
+use std::process;
+
+use eternalreckoning_server::auth::{
+    hash_password,
+    DEFAULT_ITERATIONS,
+};
+
+const USAGE: &str = "\
+Usage: hash-password [--iterations <count>]
+
+Reads a password from standard input and prints its hash for the users
+file. When standard input is a terminal, the password is prompted for
+without being echoed.";
+
+fn main() {
+    let iterations = match parse_args(env::args().skip(1).collect()) {
+        Ok(iterations) => iterations,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(1);
+        },
+    };
+
+    let password = match read_password() {
+        Ok(password) => password,
+        Err(err) => {
+            eprintln!("Failed to read password: {}", err);
+            process::exit(1);
+        },
+    };
+    if password.is_empty() {
+        eprintln!("Refusing to hash an empty password");
+        process::exit(1);
+    }
+
+    println!("{}", hash_password(&password, iterations));
+}
+
+fn parse_args(args: Vec<String>) -> Result<u32, String> {
+    let mut iterations = DEFAULT_ITERATIONS;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                iterations = args.next()
+                    .and_then(|value| value.parse::<u32>().ok())
+                    .filter(|value| *value > 0)
+                    .ok_or("Option --iterations requires a positive number")?;
+            },
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(iterations)
+}
+
+fn read_password() -> io::Result<String> {
+    let stdin = io::stdin();
+    let interactive = is_terminal();
+
+    if interactive {
+        eprint!("Password: ");
+        io::stderr().flush()?;
+    }
+
+    let echo = if interactive { disable_echo() } else { None };
+    let mut line = String::new();
+    let result = stdin.lock().read_line(&mut line);
+    if let Some(echo) = echo {
+        restore_echo(echo);
+        eprintln!();
+    }
+    result?;
+
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+
+    Ok(line)
+}
+
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+#[cfg(unix)]
+fn disable_echo() -> Option<libc::termios> {
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+            return None;
+        }
+
+        let original = termios;
+        termios.c_lflag &= !libc::ECHO;
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+            return None;
+        }
+
+        Some(original)
+    }
+}
+
+#[cfg(unix)]
+fn restore_echo(termios: libc::termios) {
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+    }
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn disable_echo() -> Option<()> {
+    None
+}
+
+#[cfg(not(unix))]
+fn restore_echo(_: ()) {}
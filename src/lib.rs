@@ -1,7 +1,11 @@
+pub mod auth;
 pub mod networking;
 pub mod simulation;
 pub mod util;
 mod server;
+mod shutdown;
+
+use std::path::Path;
 
 use failure::Error;
 use failure::format_err;
@@ -9,41 +13,82 @@ use failure::format_err;
 use eternalreckoning_core::util::config::Config;
 use eternalreckoning_core::util::logging;
 
+use util::cli::{
+    self,
+    Mode,
+    Options,
+    Overrides,
+};
+
 pub struct Bootstrap {
     pub args: Vec<String>,
     pub config: Option<String>,
 }
 
 pub fn run(bootstrap: Bootstrap) -> Result<(), Error> {
-    let config = initialize(bootstrap)?;
+    let options = Options::parse(bootstrap.args.get(1..).unwrap_or(&[]))?;
+
+    match options.mode {
+        Mode::Help => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        },
+        Mode::PrintDefaultConfig => {
+            print!("{}", toml::to_string(&util::config::Config::default())?);
+            return Ok(());
+        },
+        _ => (),
+    }
+
+    let mut overrides = Overrides::from_env()?;
+    overrides.merge(options.overrides);
+
+    let path = overrides.config.clone()
+        .or(bootstrap.config)
+        .ok_or_else(|| format_err!("no configuration file path provided"))?;
+
+    if options.mode == Mode::CheckConfig {
+        let mut config = get_configuration(&path, false)?;
+        overrides.apply(&mut config);
+        server::check(&config)?;
+
+        println!("Configuration {} is valid", path);
+        return Ok(());
+    }
+
+    let config = initialize(&path, &overrides)?;
 
     server::main(config)?;
 
     Ok(())
 }
 
-fn initialize(bootstrap: Bootstrap)
+fn initialize(path: &str, overrides: &Overrides)
     -> Result<util::config::Config, Error>
 {
-    let config = get_configuration(bootstrap)?;
-    let config = config.data;
+    let mut config = get_configuration(path, true)?;
+    overrides.apply(&mut config);
 
     logging::configure(&config.logging, "eternalreckoning_server")?;
 
     Ok(config)
 }
 
-fn get_configuration(bootstrap: Bootstrap)
-    -> Result<Config<util::config::Config>, Error>
+/// Loads the configuration file. A missing file is only replaced with the
+/// defaults when `write_default` is set; a file that fails to load is never
+/// overwritten.
+fn get_configuration(path: &str, write_default: bool)
+    -> Result<util::config::Config, Error>
 {
-    match bootstrap.config {
-        Some(path) => {
-            Config::<util::config::Config>::from_file(&path)
-                .or_else(|_| {
-                    Config::<util::config::Config>::write_default(&path)
-                })
-                .map_err(|e| { e.into() })
-        },
-        None => Err(format_err!("no configuration file path provided")),
-    }
-}
\ No newline at end of file
+    let config = if Path::new(path).exists() {
+        Config::<util::config::Config>::from_file(path)
+    } else if write_default {
+        Config::<util::config::Config>::write_default(path)
+    } else {
+        return Err(format_err!("Configuration file {} does not exist", path));
+    };
+
+    config
+        .map(|config| config.data)
+        .map_err(|e| format_err!("Failed to load configuration {}: {}", path, e))
+}
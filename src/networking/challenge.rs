@@ -0,0 +1,58 @@
+use std::net::SocketAddr;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use subtle::ConstantTimeEq;
+
+use super::signing::{
+    sign,
+    Secret,
+};
+
+const SECRET_LIFETIME: Duration = Duration::from_secs(30);
+
+pub struct Challenger {
+    current: Secret,
+    previous: Secret,
+    rotated_at: Instant,
+}
+
+impl Challenger {
+    pub fn new() -> Challenger {
+        Challenger {
+            current: rand::random(),
+            previous: rand::random(),
+            rotated_at: Instant::now(),
+        }
+    }
+
+    pub fn cookie(&mut self, addr: &SocketAddr) -> u64 {
+        self.rotate();
+
+        sign(&self.current, addr.to_string().as_bytes())
+    }
+
+    pub fn verify(&mut self, addr: &SocketAddr, cookie: u64) -> bool {
+        self.rotate();
+
+        let addr = addr.to_string();
+        let cookie = cookie.to_be_bytes();
+
+        let current = sign(&self.current, addr.as_bytes()).to_be_bytes().ct_eq(&cookie);
+        let previous = sign(&self.previous, addr.as_bytes()).to_be_bytes().ct_eq(&cookie);
+
+        (current | previous).into()
+    }
+
+    fn rotate(&mut self) {
+        if self.rotated_at.elapsed() < SECRET_LIFETIME {
+            return;
+        }
+
+        self.previous = self.current;
+        self.current = rand::random();
+        self.rotated_at = Instant::now();
+    }
+}
\ No newline at end of file
@@ -1,3 +1,9 @@
+use std::io;
+use std::net::{
+    AddrParseError,
+    SocketAddr,
+};
+
 use failure::Error;
 use failure_derive::Fail;
 
@@ -5,6 +11,16 @@ use failure_derive::Fail;
 pub enum NetworkError {
     #[fail(display = "Fatal error: {}", _0)]
     FatalError(Error),
-    #[fail(display = "Connection reset")]
-    RebuildRequired,
-}
\ No newline at end of file
+    #[fail(display = "Connection reset on socket {}", _0)]
+    RebuildRequired(usize),
+}
+
+#[derive(Debug, Fail)]
+pub enum BindError {
+    #[fail(display = "No bind address configured")]
+    NoAddress,
+    #[fail(display = "Invalid bind address {}: {}", _0, _1)]
+    InvalidAddress(String, AddrParseError),
+    #[fail(display = "Failed to bind {}: {}", _0, _1)]
+    Bind(SocketAddr, io::Error),
+}
@@ -0,0 +1,10 @@
+use super::latency::LatencyStats;
+
+/// Per-client information the network layer reports back to the simulation.
+pub enum Feedback {
+    Acknowledged(u32),
+    Latency(LatencyStats),
+    /// The client stopped acknowledging reliable messages and its connection
+    /// was closed.
+    Lost,
+}
\ No newline at end of file
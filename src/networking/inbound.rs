@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{
+    SyncSender,
+    TrySendError,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use uuid::Uuid;
+
+use eternalreckoning_core::net::operation::Operation;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// Operations held back while the simulation catches up. Further ones are
+/// dropped.
+const MAX_HELD: usize = 4096;
+
+#[derive(Default)]
+struct Dropped {
+    operations: u64,
+    held: u64,
+    overflow: u64,
+}
+
+/// Hands received operations to the simulation over a bounded channel.
+///
+/// When the channel is full, operations that are superseded by the next one
+/// a client sends (inputs, syncs, pongs) are dropped. Everything else is held
+/// back, up to `MAX_HELD` operations, while the reader keeps reading so acks
+/// and pongs are still processed. Once the simulation has stopped,
+/// everything is dropped.
+pub struct InboundQueue {
+    tx: SyncSender<(Uuid, Operation)>,
+    pending: VecDeque<(Uuid, Operation)>,
+    dropped: Dropped,
+    reported_at: Instant,
+    closed: bool,
+}
+
+impl InboundQueue {
+    pub fn new(tx: SyncSender<(Uuid, Operation)>) -> InboundQueue {
+        InboundQueue {
+            tx,
+            pending: VecDeque::new(),
+            dropped: Dropped::default(),
+            reported_at: Instant::now(),
+            closed: false,
+        }
+    }
+
+    /// Whether the simulation has stopped taking operations.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Whether operations are held back waiting for the simulation.
+    pub fn is_backlogged(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn push(&mut self, id: Uuid, op: Operation) {
+        if self.closed {
+            return;
+        }
+
+        if !self.pending.is_empty() {
+            self.hold(id, op);
+            return;
+        }
+
+        match self.tx.try_send((id, op)) {
+            Ok(()) => (),
+            Err(TrySendError::Full((id, op))) => self.hold(id, op),
+            Err(TrySendError::Disconnected(_)) => self.close(),
+        }
+    }
+
+    /// Sends as much of the held back backlog as the channel has room for.
+    /// Returns whether it was emptied.
+    pub fn flush(&mut self) -> bool {
+        self.report(Instant::now());
+
+        while let Some((id, op)) = self.pending.pop_front() {
+            match self.tx.try_send((id, op)) {
+                Ok(()) => (),
+                Err(TrySendError::Full(item)) => {
+                    self.pending.push_front(item);
+                    return false;
+                },
+                Err(TrySendError::Disconnected(_)) => {
+                    self.close();
+                    return true;
+                },
+            }
+        }
+
+        true
+    }
+
+    fn close(&mut self) {
+        log::info!("Simulation stopped, ignoring further operations");
+        self.closed = true;
+        self.pending.clear();
+    }
+
+    fn hold(&mut self, id: Uuid, op: Operation) {
+        match op {
+            Operation::ClInput(_) | Operation::ClSync(_) | Operation::ClPong(_) => {
+                self.dropped.operations += 1;
+            },
+            _ if self.pending.len() >= MAX_HELD => {
+                self.dropped.overflow += 1;
+            },
+            op => {
+                self.dropped.held += 1;
+                self.pending.push_back((id, op));
+            },
+        }
+    }
+
+    fn report(&mut self, now: Instant) {
+        if now.duration_since(self.reported_at) < REPORT_INTERVAL {
+            return;
+        }
+        self.reported_at = now;
+
+        let dropped = std::mem::take(&mut self.dropped);
+        if dropped.operations + dropped.held + dropped.overflow == 0 {
+            return;
+        }
+
+        log::warn!(
+            "Inbound queue full in the last {}s: {} operations dropped, {} held back, {} dropped with the backlog full",
+            REPORT_INTERVAL.as_secs(),
+            dropped.operations,
+            dropped.held,
+            dropped.overflow
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+
+    use eternalreckoning_core::net::operation;
+
+    use super::*;
+
+    fn disconnect() -> Operation {
+        Operation::DisconnectMessage(operation::DisconnectMessage { reason: None })
+    }
+
+    #[test]
+    fn holds_operations_until_the_channel_has_room() {
+        let (tx, rx) = sync_channel(1);
+        let mut queue = InboundQueue::new(tx);
+        let id = Uuid::new_v4();
+
+        queue.push(id, disconnect());
+        queue.push(id, disconnect());
+        assert!(queue.is_backlogged());
+        assert!(!queue.flush());
+
+        rx.recv().unwrap();
+        assert!(queue.flush());
+        assert!(!queue.is_backlogged());
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn bounds_the_held_operations() {
+        let (tx, rx) = sync_channel(1);
+        let mut queue = InboundQueue::new(tx);
+        let id = Uuid::new_v4();
+
+        for _ in 0..MAX_HELD + 10 {
+            queue.push(id, disconnect());
+        }
+        // The first one went straight into the channel.
+        assert_eq!(queue.pending.len(), MAX_HELD);
+        assert_eq!(queue.dropped.overflow, 9);
+
+        drop(rx);
+        assert!(queue.flush());
+        assert!(queue.is_closed());
+    }
+}
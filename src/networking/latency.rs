@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_PENDING: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct LatencyStats {
+    pub rtt: Duration,
+    pub jitter: Duration,
+    /// Client clock minus server clock, in milliseconds.
+    pub clock_offset: i64,
+}
+
+/// Smoothed round trip and jitter estimates in the style of RFC 6298, fed
+/// by ping/pong exchanges.
+pub struct RttEstimator {
+    next_sequence: u32,
+    pending: VecDeque<(u32, Instant)>,
+    srtt: f64,
+    rttvar: f64,
+    offset: f64,
+    sampled: bool,
+}
+
+impl RttEstimator {
+    pub fn new() -> RttEstimator {
+        RttEstimator {
+            next_sequence: 0,
+            pending: VecDeque::new(),
+            srtt: 0.0,
+            rttvar: 0.0,
+            offset: 0.0,
+            sampled: false,
+        }
+    }
+
+    pub fn ping(&mut self, now: Instant) -> u32 {
+        let sequence = self.next_sequence;
+        self.next_sequence = sequence.wrapping_add(1);
+
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((sequence, now));
+
+        sequence
+    }
+
+    pub fn pong(
+        &mut self,
+        sequence: u32,
+        now: Instant,
+        server_time: u64,
+        client_time: u64,
+    ) -> Option<LatencyStats>
+    {
+        let index = self.pending.iter().position(|(pending, _)| *pending == sequence)?;
+        let (_, sent_at) = self.pending.remove(index)?;
+        self.pending.drain(..index);
+
+        let sample = now.duration_since(sent_at).as_secs_f64();
+        let offset = client_time as f64 - (server_time as f64 + sample * 1000.0 / 2.0);
+
+        if self.sampled {
+            self.rttvar = 0.75 * self.rttvar + 0.25 * (self.srtt - sample).abs();
+            self.srtt = 0.875 * self.srtt + 0.125 * sample;
+            self.offset = 0.875 * self.offset + 0.125 * offset;
+        } else {
+            self.srtt = sample;
+            self.rttvar = sample / 2.0;
+            self.offset = offset;
+            self.sampled = true;
+        }
+
+        Some(self.stats())
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        LatencyStats {
+            rtt: Duration::from_secs_f64(self.srtt),
+            jitter: Duration::from_secs_f64(self.rttvar),
+            clock_offset: self.offset.round() as i64,
+        }
+    }
+}
+
+impl Default for RttEstimator {
+    fn default() -> RttEstimator {
+        RttEstimator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(duration: Duration) -> f64 {
+        duration.as_secs_f64() * 1000.0
+    }
+
+    fn sample(estimator: &mut RttEstimator, start: Instant, rtt_ms: u64) -> LatencyStats {
+        let sequence = estimator.ping(start);
+        estimator.pong(sequence, start + Duration::from_millis(rtt_ms), 0, rtt_ms / 2)
+            .unwrap()
+    }
+
+    #[test]
+    fn first_sample_sets_srtt_and_half_rttvar() {
+        let stats = sample(&mut RttEstimator::new(), Instant::now(), 100);
+
+        assert!((ms(stats.rtt) - 100.0).abs() < 1e-6);
+        assert!((ms(stats.jitter) - 50.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn later_samples_are_smoothed() {
+        let mut estimator = RttEstimator::new();
+        let start = Instant::now();
+        sample(&mut estimator, start, 100);
+        let stats = sample(&mut estimator, start, 200);
+
+        // RTTVAR = 3/4 * 50 + 1/4 * |100 - 200|, SRTT = 7/8 * 100 + 1/8 * 200
+        assert!((ms(stats.jitter) - 62.5).abs() < 1e-6);
+        assert!((ms(stats.rtt) - 112.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn estimates_the_clock_offset() {
+        let mut estimator = RttEstimator::new();
+        let start = Instant::now();
+        let sequence = estimator.ping(start);
+
+        let stats = estimator.pong(sequence, start + Duration::from_millis(100), 1000, 1550)
+            .unwrap();
+        assert_eq!(stats.clock_offset, 500);
+    }
+
+    #[test]
+    fn ignores_unknown_and_superseded_pongs() {
+        let mut estimator = RttEstimator::new();
+        let start = Instant::now();
+        let first = estimator.ping(start);
+        let second = estimator.ping(start);
+
+        assert!(estimator.pong(second + 1, start, 0, 0).is_none());
+        assert!(estimator.pong(second, start, 0, 0).is_some());
+        assert!(estimator.pong(first, start, 0, 0).is_none());
+    }
+
+    #[test]
+    fn forgets_the_oldest_unanswered_pings() {
+        let mut estimator = RttEstimator::new();
+        let start = Instant::now();
+        let first = estimator.ping(start);
+        for _ in 0..MAX_PENDING {
+            estimator.ping(start);
+        }
+
+        assert!(estimator.pong(first, start, 0, 0).is_none());
+    }
+}
@@ -1,7 +1,32 @@
+mod challenge;
 mod error;
+mod feedback;
+mod inbound;
+mod latency;
+mod outbound;
+mod packet;
+mod ratelimit;
+mod reliability;
 mod server;
+mod session;
+mod socket;
+mod signing;
 mod state;
 mod reader;
 mod writer;
 
-pub use server::Server;
\ No newline at end of file
+pub use error::BindError;
+pub use feedback::Feedback;
+pub use latency::LatencyStats;
+pub use outbound::Outbound;
+pub use packet::{
+    Ack,
+    Header,
+    Packet,
+    PacketCodec,
+};
+pub use ratelimit::RateLimitConfig;
+pub use reliability::Delivery;
+pub use server::Server;
+pub use session::SessionKey;
+pub use socket::parse_addresses;
\ No newline at end of file
@@ -0,0 +1,10 @@
+use eternalreckoning_core::net::operation::Operation;
+
+use super::reliability::Delivery;
+
+/// What the simulation asks of the network layer for a client.
+pub enum Outbound {
+    Send(Delivery, Operation),
+    /// The client is gone; forget its address and connection state.
+    Remove,
+}
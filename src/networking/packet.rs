@@ -0,0 +1,222 @@
+use std::io;
+
+use bytes::{
+    Bytes,
+    BytesMut,
+};
+use tokio::codec::{
+    Decoder,
+    Encoder,
+};
+
+const FLAG_PAYLOAD: u8 = 0x01;
+const FLAG_RELIABLE: u8 = 0x02;
+const FLAG_ACK: u8 = 0x04;
+
+const HEADER_LENGTH: usize = 9;
+const RELIABLE_ID_LENGTH: usize = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ack {
+    pub sequence: u16,
+    pub bits: u32,
+}
+
+impl Ack {
+    pub fn contains(&self, sequence: u16) -> bool {
+        if sequence == self.sequence {
+            return true;
+        }
+
+        let distance = self.sequence.wrapping_sub(sequence) as u32;
+        if distance == 0 || distance > 32 {
+            return false;
+        }
+
+        self.bits & (1 << (distance - 1)) != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Header {
+    pub sequence: u16,
+    pub ack: Option<Ack>,
+    pub reliable_id: Option<u16>,
+}
+
+pub struct Packet {
+    pub header: Header,
+    pub payload: Option<Bytes>,
+}
+
+/// Frames a datagram as a reliability header followed by an optional
+/// payload. Payloads are opaque here; they are encoded with
+/// `EternalReckoningCodec` by the reader and writer.
+pub struct PacketCodec;
+
+impl Decoder for PacketCodec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>, io::Error> {
+        if src.len() < HEADER_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "packet header truncated",
+            ));
+        }
+
+        let raw = src.split_to(HEADER_LENGTH);
+        let sequence = u16::from_be_bytes([raw[0], raw[1]]);
+        let ack_sequence = u16::from_be_bytes([raw[2], raw[3]]);
+        let ack_bits = u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]);
+        let flags = raw[8];
+
+        let ack = match flags & FLAG_ACK {
+            0 => None,
+            _ => Some(Ack { sequence: ack_sequence, bits: ack_bits }),
+        };
+
+        let reliable_id = if flags & FLAG_RELIABLE != 0 {
+            if src.len() < RELIABLE_ID_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "packet reliable id truncated",
+                ));
+            }
+            let raw = src.split_to(RELIABLE_ID_LENGTH);
+            Some(u16::from_be_bytes([raw[0], raw[1]]))
+        } else {
+            None
+        };
+
+        let payload = match flags & FLAG_PAYLOAD {
+            0 => None,
+            _ => Some(src.take().freeze()),
+        };
+
+        Ok(Some(Packet {
+            header: Header { sequence, ack, reliable_id },
+            payload,
+        }))
+    }
+}
+
+impl Encoder for PacketCodec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let header = packet.header;
+
+        let mut flags = 0;
+        if packet.payload.is_some() {
+            flags |= FLAG_PAYLOAD;
+        }
+        if header.reliable_id.is_some() {
+            flags |= FLAG_RELIABLE;
+        }
+        let ack = match header.ack {
+            Some(ack) => {
+                flags |= FLAG_ACK;
+                ack
+            },
+            None => Ack { sequence: 0, bits: 0 },
+        };
+
+        dst.reserve(
+            HEADER_LENGTH
+            + RELIABLE_ID_LENGTH
+            + packet.payload.as_ref().map_or(0, |payload| payload.len())
+        );
+        dst.extend_from_slice(&header.sequence.to_be_bytes());
+        dst.extend_from_slice(&ack.sequence.to_be_bytes());
+        dst.extend_from_slice(&ack.bits.to_be_bytes());
+        dst.extend_from_slice(&[flags]);
+        if let Some(id) = header.reliable_id {
+            dst.extend_from_slice(&id.to_be_bytes());
+        }
+        if let Some(payload) = packet.payload {
+            dst.extend_from_slice(&payload);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(packet: Packet) -> Packet {
+        let mut buffer = BytesMut::new();
+        PacketCodec.encode(packet, &mut buffer).unwrap();
+        PacketCodec.decode(&mut buffer).unwrap().unwrap()
+    }
+
+    #[test]
+    fn round_trips_every_header_field() {
+        let decoded = round_trip(Packet {
+            header: Header {
+                sequence: 65535,
+                ack: Some(Ack { sequence: 40000, bits: 0x8000_0001 }),
+                reliable_id: Some(513),
+            },
+            payload: Some(Bytes::from_static(b"payload")),
+        });
+
+        assert_eq!(decoded.header.sequence, 65535);
+        let ack = decoded.header.ack.unwrap();
+        assert_eq!(ack.sequence, 40000);
+        assert_eq!(ack.bits, 0x8000_0001);
+        assert_eq!(decoded.header.reliable_id, Some(513));
+        assert_eq!(decoded.payload, Some(Bytes::from_static(b"payload")));
+    }
+
+    #[test]
+    fn round_trips_a_bare_header() {
+        let decoded = round_trip(Packet {
+            header: Header { sequence: 7, ack: None, reliable_id: None },
+            payload: None,
+        });
+
+        assert_eq!(decoded.header.sequence, 7);
+        assert!(decoded.header.ack.is_none());
+        assert!(decoded.header.reliable_id.is_none());
+        assert!(decoded.payload.is_none());
+    }
+
+    #[test]
+    fn keeps_an_empty_payload_distinct_from_none() {
+        let decoded = round_trip(Packet {
+            header: Header { sequence: 0, ack: None, reliable_id: Some(0) },
+            payload: Some(Bytes::new()),
+        });
+
+        assert_eq!(decoded.header.reliable_id, Some(0));
+        assert_eq!(decoded.payload, Some(Bytes::new()));
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let mut short = BytesMut::from(&[0u8; HEADER_LENGTH - 1][..]);
+        assert!(PacketCodec.decode(&mut short).is_err());
+
+        let mut header = [0u8; HEADER_LENGTH + 1];
+        header[HEADER_LENGTH - 1] = FLAG_RELIABLE;
+        let mut missing_id = BytesMut::from(&header[..]);
+        assert!(PacketCodec.decode(&mut missing_id).is_err());
+    }
+
+    #[test]
+    fn acks_only_the_recorded_history() {
+        let ack = Ack { sequence: 1, bits: 0b101 };
+
+        assert!(ack.contains(1));
+        assert!(ack.contains(0));
+        assert!(!ack.contains(65535));
+        assert!(ack.contains(65534));
+        assert!(!ack.contains(2));
+        assert!(!ack.contains(1u16.wrapping_sub(33)));
+    }
+}
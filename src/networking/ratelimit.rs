@@ -0,0 +1,368 @@
+use std::collections::hash_map::{
+    Entry,
+    HashMap,
+};
+use std::net::SocketAddr;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use serde::{Serialize, Deserialize};
+
+const VIOLATION_WINDOW: Duration = Duration::from_secs(1);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RateLimitConfig {
+    pub client_rate: f64,
+    pub client_burst: f64,
+    pub global_rate: f64,
+    pub global_burst: f64,
+    pub blocklist_threshold: u32,
+    pub blocklist_ms: u64,
+    /// Addresses tracked at once. Packets from new addresses are dropped
+    /// while the table is full.
+    pub max_addresses: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            client_rate: 120.0,
+            client_burst: 240.0,
+            global_rate: 20000.0,
+            global_burst: 40000.0,
+            blocklist_threshold: 200,
+            blocklist_ms: 30000,
+            max_addresses: 65536,
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64, now: Instant) -> TokenBucket {
+        TokenBucket { tokens: burst, updated: now }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+
+    fn take(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        self.refill(rate, burst, now);
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+struct ClientLimit {
+    bucket: TokenBucket,
+    violations: u32,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Dropped {
+    client: u64,
+    global: u64,
+    blocked: u64,
+    full: u64,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    global: TokenBucket,
+    clients: HashMap<SocketAddr, ClientLimit>,
+    dropped: Dropped,
+    pruned_at: Instant,
+    reported_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        let now = Instant::now();
+
+        RateLimiter {
+            global: TokenBucket::new(config.global_burst, now),
+            config,
+            clients: HashMap::new(),
+            dropped: Dropped::default(),
+            pruned_at: now,
+            reported_at: now,
+        }
+    }
+
+    pub fn allow(&mut self, addr: &SocketAddr) -> bool {
+        self.check(addr, Instant::now())
+    }
+
+    fn check(&mut self, addr: &SocketAddr, now: Instant) -> bool {
+        self.prune(now);
+        self.report(now);
+
+        let config = &self.config;
+        let full = self.clients.len() >= config.max_addresses;
+
+        // A new address only gets an entry once its packet has passed the
+        // global limit, so spoofed sources can't grow the table faster
+        // than that limit allows.
+        let mut charged = false;
+        let client = match self.clients.entry(*addr) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                if full {
+                    self.dropped.full += 1;
+                    return false;
+                }
+                if !self.global.take(config.global_rate, config.global_burst, now) {
+                    self.dropped.global += 1;
+                    return false;
+                }
+                charged = true;
+
+                entry.insert(ClientLimit {
+                    bucket: TokenBucket::new(config.client_burst, now),
+                    violations: 0,
+                    window_start: now,
+                    blocked_until: None,
+                })
+            },
+        };
+
+        if let Some(until) = client.blocked_until {
+            if now < until {
+                self.dropped.blocked += 1;
+                return false;
+            }
+            client.blocked_until = None;
+        }
+
+        if !client.bucket.take(config.client_rate, config.client_burst, now) {
+            self.dropped.client += 1;
+
+            if now.duration_since(client.window_start) > VIOLATION_WINDOW {
+                client.violations = 0;
+                client.window_start = now;
+            }
+            client.violations += 1;
+
+            if client.violations >= config.blocklist_threshold {
+                log::warn!(
+                    "Blocking {} for {} ms after repeated rate limit violations",
+                    addr,
+                    config.blocklist_ms
+                );
+                client.violations = 0;
+                client.blocked_until = Some(
+                    now + Duration::from_millis(config.blocklist_ms)
+                );
+            }
+
+            return false;
+        }
+
+        if !charged && !self.global.take(config.global_rate, config.global_burst, now) {
+            self.dropped.global += 1;
+            return false;
+        }
+
+        true
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if now.duration_since(self.pruned_at) < PRUNE_INTERVAL {
+            return;
+        }
+        self.pruned_at = now;
+
+        let config = &self.config;
+        self.clients.retain(|_, client| {
+            if let Some(until) = client.blocked_until {
+                if now < until {
+                    return true;
+                }
+            }
+
+            client.bucket.refill(config.client_rate, config.client_burst, now);
+            client.bucket.tokens < config.client_burst
+        });
+    }
+
+    fn report(&mut self, now: Instant) {
+        if now.duration_since(self.reported_at) < REPORT_INTERVAL {
+            return;
+        }
+        self.reported_at = now;
+
+        let dropped = std::mem::take(&mut self.dropped);
+        if dropped.client + dropped.global + dropped.blocked + dropped.full == 0 {
+            return;
+        }
+
+        log::warn!(
+            "Rate limited packets in the last {}s: {} per-client, {} global, {} blocklisted, \
+             {} from new addresses while {} were tracked",
+            REPORT_INTERVAL.as_secs(),
+            dropped.client,
+            dropped.global,
+            dropped.blocked,
+            dropped.full,
+            self.clients.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            client_rate: 10.0,
+            client_burst: 5.0,
+            global_rate: 1000.0,
+            global_burst: 1000.0,
+            blocklist_threshold: 1000,
+            blocklist_ms: 1000,
+            max_addresses: 1000,
+        }
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn refills_up_to_the_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, now);
+
+        assert!(bucket.take(10.0, 2.0, now));
+        assert!(bucket.take(10.0, 2.0, now));
+        assert!(!bucket.take(10.0, 2.0, now));
+
+        assert!(bucket.take(10.0, 2.0, now + Duration::from_millis(100)));
+        assert!(!bucket.take(10.0, 2.0, now + Duration::from_millis(100)));
+
+        bucket.refill(10.0, 2.0, now + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn limits_each_client_separately() {
+        let mut limiter = RateLimiter::new(config());
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert!(limiter.check(&addr(1), now));
+        }
+        assert!(!limiter.check(&addr(1), now));
+        assert!(limiter.check(&addr(2), now));
+
+        assert!(limiter.check(&addr(1), now + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn limits_all_clients_together() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            global_rate: 0.0,
+            global_burst: 3.0,
+            ..config()
+        });
+        let now = Instant::now();
+
+        for port in 0..3 {
+            assert!(limiter.check(&addr(port), now));
+        }
+        assert!(!limiter.check(&addr(3), now));
+    }
+
+    #[test]
+    fn blocks_repeat_offenders_until_the_block_expires() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            client_burst: 1.0,
+            blocklist_threshold: 3,
+            ..config()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check(&addr(1), now));
+        for _ in 0..3 {
+            assert!(!limiter.check(&addr(1), now));
+        }
+
+        // The bucket has refilled, but the address is still blocked.
+        let refilled = now + Duration::from_millis(500);
+        assert!(!limiter.check(&addr(1), refilled));
+        assert!(limiter.check(&addr(2), refilled));
+
+        assert!(limiter.check(&addr(1), now + Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn forgets_violations_outside_the_window() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            client_rate: 0.0,
+            client_burst: 1.0,
+            blocklist_threshold: 2,
+            ..config()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check(&addr(1), now));
+        assert!(!limiter.check(&addr(1), now));
+        assert!(!limiter.check(&addr(1), now + VIOLATION_WINDOW * 2));
+
+        let client = &limiter.clients[&addr(1)];
+        assert_eq!(client.violations, 1);
+        assert!(client.blocked_until.is_none());
+    }
+
+    #[test]
+    fn bounds_the_address_table() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            max_addresses: 100,
+            ..config()
+        });
+        let now = Instant::now();
+
+        for port in 0..1000 {
+            limiter.check(&addr(port), now);
+        }
+        assert_eq!(limiter.clients.len(), 100);
+
+        // Known addresses keep being served.
+        assert!(limiter.check(&addr(0), now));
+    }
+
+    #[test]
+    fn admits_new_addresses_only_within_the_global_limit() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            global_rate: 0.0,
+            global_burst: 10.0,
+            ..config()
+        });
+        let now = Instant::now();
+
+        for port in 0..1000 {
+            limiter.check(&addr(port), now);
+        }
+        assert_eq!(limiter.clients.len(), 10);
+    }
+}
@@ -1,13 +1,28 @@
+use std::io;
 use std::net::SocketAddr;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{
+    Sender,
+    SyncSender,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
 
+use bytes::{
+    Bytes,
+    BytesMut,
+};
 use failure::{
     format_err,
     Error,
 };
-use futures::stream::{
-    Stream,
-    SplitStream,
+use futures::stream::Stream;
+use futures::sync::mpsc;
+use futures::task;
+use tokio::codec::{
+    Decoder,
+    Encoder,
 };
 use tokio::net::UdpFramed;
 use tokio::prelude::{
@@ -15,74 +30,364 @@ use tokio::prelude::{
     Future,
     Poll,
 };
+use tokio::timer::Delay;
 use uuid::Uuid;
 
 use eternalreckoning_core::net::{
     codec::EternalReckoningCodec,
-    operation::Operation,
+    operation::{
+        self,
+        Operation,
+    },
 };
 
+use crate::shutdown;
+
 use super::error::NetworkError;
-use super::state::SharedState;
+use super::feedback::Feedback;
+use super::inbound::InboundQueue;
+use super::latency::RttEstimator;
+use super::packet::{
+    Header,
+    Packet,
+    PacketCodec,
+};
+use super::reliability::Channel;
+use super::session::SessionKey;
+use super::socket;
+use super::state::{
+    Route,
+    SharedState,
+    State,
+};
 
-pub type Tx = Sender<(Uuid, Operation)>;
+pub type Tx = SyncSender<(Uuid, Operation)>;
+pub type FeedbackTx = Sender<(Uuid, Feedback)>;
+pub type DirectTx = mpsc::Sender<(Packet, Route)>;
+
+/// Challenges waiting to be sent. Further ones are dropped, and the clients
+/// retry their connect.
+pub const DIRECT_CAPACITY: usize = 1024;
+
+const BACKLOG_RETRY: Duration = Duration::from_millis(5);
+/// Datagrams read before giving the writer a turn.
+const READ_BUDGET: usize = 256;
 
 pub struct Reader {
+    socket: usize,
     shared: SharedState,
-    stream: SplitStream<UdpFramed<EternalReckoningCodec>>,
-    tx: Tx,
+    stream: Option<UdpFramed<PacketCodec>>,
+    codec: EternalReckoningCodec,
+    sessions: SessionKey,
+    inbound: InboundQueue,
+    retry: Option<Delay>,
+    feedback_tx: FeedbackTx,
+    direct_tx: DirectTx,
 }
 
 impl Reader {
     pub fn new(
+        socket: usize,
         shared: SharedState,
-        stream: SplitStream<UdpFramed<EternalReckoningCodec>>,
         tx: Tx,
+        feedback_tx: FeedbackTx,
+        direct_tx: DirectTx,
+        sessions: SessionKey,
     ) -> Reader
     {
-        Reader { shared, stream, tx }
+        let codec = EternalReckoningCodec;
+
+        Reader {
+            socket,
+            shared,
+            stream: None,
+            codec,
+            sessions,
+            inbound: InboundQueue::new(tx),
+            retry: None,
+            feedback_tx,
+            direct_tx,
+        }
+    }
+
+    /// Sets the socket to read from, again after a rebuild.
+    pub fn attach(&mut self, stream: UdpFramed<PacketCodec>) {
+        self.stream = Some(stream);
+    }
+
+    pub fn detach(&mut self) {
+        self.stream = None;
+    }
+
+    fn challenge(&mut self, shared: &mut State, addr: SocketAddr) -> Result<(), Error> {
+        let cookie = shared.challenger.cookie(&addr);
+        let op = Operation::SvConnectChallenge(
+            operation::SvConnectChallenge { cookie }
+        );
+
+        let mut payload = BytesMut::new();
+        self.codec.encode(op, &mut payload)?;
+
+        let packet = Packet {
+            header: Header {
+                sequence: 0,
+                ack: None,
+                reliable_id: None,
+            },
+            payload: Some(payload.freeze()),
+        };
+
+        match self.direct_tx.try_send((packet, Route { socket: self.socket, addr })) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.is_full() => {
+                log::debug!("Dropped challenge for {}: too many pending", addr);
+                Ok(())
+            },
+            Err(err) => Err(format_err!("Communication failure: {}", err)),
+        }
+    }
+
+    /// Reports to the simulation, which only goes away while shutting down.
+    fn feedback(&self, id: Uuid, feedback: Feedback) {
+        if self.feedback_tx.send((id, feedback)).is_err() {
+            log::debug!("Dropped feedback for {}: simulation stopped", id);
+        }
     }
 
-    fn receive(&mut self, addr: SocketAddr, op: Operation) -> Result<(), Error> {
-        let mut shared = self.shared.lock()
+    fn decode(&mut self, addr: &SocketAddr, payload: Bytes) -> Option<Operation> {
+        let mut buffer = BytesMut::from(payload);
+
+        match self.codec.decode(&mut buffer) {
+            Ok(Some(op)) => Some(op),
+            Ok(None) => {
+                log::warn!("Received incomplete operation from: {}", addr);
+                None
+            },
+            Err(err) => {
+                log::warn!("Received invalid operation from {}: {}", addr, err);
+                None
+            },
+        }
+    }
+
+    fn receive(&mut self, addr: SocketAddr, packet: Packet) -> Result<(), Error> {
+        let shared = self.shared.clone();
+        let mut shared = shared.lock()
             .map_err(|err| {
                 format_err!("Failed to access shared state: {}", err)
             })?;
 
+        if !shared.limiter.allow(&addr) {
+            return Ok(());
+        }
+
         if let Some(id) = shared.addr_to_id.get(&addr) {
             let id = *id;
-            match op {
-                Operation::DisconnectMessage => {
-                    shared.addr_to_id.remove(&addr);
-                    shared.id_to_addr.remove(&id);
+            let header = packet.header;
+            let raw = packet.payload.clone();
+            let (payloads, acknowledged) = match shared.channels.get_mut(&id) {
+                Some(channel) => (
+                    channel.receive(&header, packet.payload),
+                    channel.take_acknowledged(),
+                ),
+                None => {
+                    log::error!("No channel for client {}", id);
+                    return Ok(());
                 },
-                _ => (),
+            };
+
+            // The simulation is gone while closing; only acks matter now.
+            if shared.closing || self.inbound.is_closed() {
+                return Ok(());
+            }
+
+            for tag in acknowledged {
+                self.feedback(id, Feedback::Acknowledged(tag));
+            }
+
+            // A client restarting from the same address starts a new channel,
+            // which this one is likely to take for old packets.
+            if payloads.is_empty() && header.reliable_id.is_none() {
+                if let Some(connect) = raw.clone().and_then(|payload| self.peek_connect(payload)) {
+                    return self.reconnect(&mut shared, addr, &connect, &header, raw);
+                }
             }
-            self.tx.send((id, op))
-                .map_err(|err| {
-                    format_err!("Communication failure: {}", err)
-                })?;
+
+            for payload in payloads {
+                let op = match self.decode(&addr, payload) {
+                    Some(op) => op,
+                    None => continue,
+                };
+
+                match op {
+                    Operation::ClConnectMessage(ref connect) => {
+                        return self.reconnect(&mut shared, addr, connect, &header, raw);
+                    },
+                    Operation::ClPong(ref pong) => {
+                        let stats = shared.latency.get_mut(&id)
+                            .and_then(|estimator| estimator.pong(
+                                pong.sequence,
+                                Instant::now(),
+                                pong.server_time,
+                                pong.client_time,
+                            ));
+                        if let Some(stats) = stats {
+                            self.feedback(id, Feedback::Latency(stats));
+                        }
+                    },
+                    Operation::DisconnectMessage(_) => {
+                        shared.remove(&id);
+                    },
+                    _ => (),
+                }
+                self.inbound.push(id, op);
+            }
+
+            Ok(())
         } else {
-            match op {
-                Operation::ClConnectMessage(_) => {
-                    let id = Uuid::new_v4();
-
-                    shared.addr_to_id.insert(addr, id);
-                    shared.id_to_addr.insert(id, addr);
-                    
-                    self.tx.send((id, op))
-                        .map_err(|err| {
-                            format_err!("Communication failure: {}", err)
-                        })?;
-                },
-                _ => {
-                    log::warn!("Received packet from unknown client: {}", &addr);
-                },
+            if shared.closing || shutdown::requested() || self.inbound.is_closed() {
+                log::debug!("Ignoring packet from {} while closing", &addr);
+                return Ok(());
             }
+
+            self.handshake(&mut shared, addr, &packet.header, packet.payload)
         }
+    }
+
+    fn peek_connect(&mut self, payload: Bytes) -> Option<operation::ClConnectMessage> {
+        match self.codec.decode(&mut BytesMut::from(payload)) {
+            Ok(Some(Operation::ClConnectMessage(connect))) => Some(connect),
+            _ => None,
+        }
+    }
+
+    /// Handles a connect from an address that already has a connection. A
+    /// client restarting its connection has to ask for a new challenge first,
+    /// so that neither a spoofed nor a duplicated connect ends the current
+    /// one.
+    fn reconnect(
+        &mut self,
+        shared: &mut State,
+        addr: SocketAddr,
+        connect: &operation::ClConnectMessage,
+        header: &Header,
+        payload: Option<Bytes>,
+    ) -> Result<(), Error>
+    {
+        if shutdown::requested() {
+            return Ok(());
+        }
+
+        if connect.cookie.is_none() {
+            shared.rechallenged.insert(addr);
+            return self.challenge(shared, addr);
+        }
+
+        if !shared.rechallenged.contains(&addr) {
+            log::debug!("Ignoring repeated connect from: {}", &addr);
+            return Ok(());
+        }
+
+        self.handshake(shared, addr, header, payload)
+    }
+
+    fn handshake(
+        &mut self,
+        shared: &mut State,
+        addr: SocketAddr,
+        header: &Header,
+        payload: Option<Bytes>,
+    ) -> Result<(), Error>
+    {
+        let mut channel = Channel::new();
+        let op = channel.receive(header, payload)
+            .into_iter()
+            .next()
+            .and_then(|payload| self.decode(&addr, payload));
+
+        let connect = match op {
+            Some(Operation::ClConnectMessage(connect)) => connect,
+            _ => {
+                log::warn!("Received packet from unknown client: {}", &addr);
+                return Ok(());
+            },
+        };
+
+        let cookie = match connect.cookie {
+            Some(cookie) => cookie,
+            None => return self.challenge(shared, addr),
+        };
+
+        if !shared.challenger.verify(&addr, cookie) {
+            log::warn!("Invalid challenge response from: {}", &addr);
+            return Ok(());
+        }
+
+        let id = match connect.session {
+            Some(ref session) => {
+                if !self.sessions.verify(&session.uuid, session.token) {
+                    log::warn!("Invalid session token from: {}", &addr);
+                    return Ok(());
+                }
+                session.uuid
+            },
+            None => Uuid::new_v4(),
+        };
+
+        if let Some(previous) = shared.addr_to_id.get(&addr).copied() {
+            log::info!("Client {} restarted its connection from {}", previous, addr);
+            shared.remove(&previous);
+            if previous != id {
+                self.inbound.push(
+                    previous,
+                    Operation::DisconnectMessage(operation::DisconnectMessage { reason: None })
+                );
+            }
+        }
+
+        if let Some(previous) = shared.remove(&id) {
+            log::info!("Client {} moved from {} to {}", id, previous.addr, addr);
+        }
+
+        shared.addr_to_id.insert(addr, id);
+        shared.id_to_route.insert(id, Route { socket: self.socket, addr });
+        shared.channels.insert(id, channel);
+        shared.latency.insert(id, RttEstimator::new());
+
+        self.inbound.push(id, Operation::ClConnectMessage(connect));
 
         Ok(())
     }
+
+    /// Hands operations held back by a full inbound queue to the simulation,
+    /// and schedules another try while some remain. Reading carries on
+    /// meanwhile.
+    fn flush_backlog(&mut self) -> Result<(), Error> {
+        let closing = self.shared.lock()
+            .map_err(|err| {
+                format_err!("Failed to access shared state: {}", err)
+            })?
+            .closing;
+        if closing || self.inbound.is_closed() {
+            self.retry = None;
+            return Ok(());
+        }
+
+        loop {
+            if self.inbound.flush() {
+                self.retry = None;
+                return Ok(());
+            }
+
+            let retry = self.retry.get_or_insert_with(|| {
+                Delay::new(Instant::now() + BACKLOG_RETRY)
+            });
+            if retry.poll()?.is_not_ready() {
+                return Ok(());
+            }
+            self.retry = None;
+        }
+    }
 }
 
 impl Future for Reader {
@@ -90,26 +395,48 @@ impl Future for Reader {
     type Error = NetworkError;
 
     fn poll(&mut self) -> Poll<(), NetworkError> {
-        loop {
-            match self.stream.poll() {
+        for _ in 0..READ_BUDGET {
+            if self.retry.is_some() || self.inbound.is_backlogged() {
+                self.flush_backlog()
+                    .map_err(|err| NetworkError::FatalError(
+                        format_err!("Reader error: {}", err)
+                    ))?;
+            }
+
+            let polled = match self.stream {
+                Some(ref mut stream) => stream.poll(),
+                None => return Ok(Async::NotReady),
+            };
+
+            match polled {
                 Ok(Async::NotReady) => {
                     return Ok(Async::NotReady);
                 },
-                Ok(Async::Ready(Some((op, addr)))) => {
-                    self.receive(addr, op)
+                Ok(Async::Ready(Some((packet, addr)))) => {
+                    self.receive(addr, packet)
                         .map_err(|err| NetworkError::FatalError(
                             format_err!("Reader error: {}", err)
                         ))?;
                 },
                 Ok(Async::Ready(None)) => {
-                    return Err(NetworkError::RebuildRequired);
+                    return Err(NetworkError::RebuildRequired(self.socket));
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+                    log::warn!("Dropped malformed packet: {}", err);
+                },
+                Err(ref err) if socket::is_datagram_error(err) => {
+                    log::warn!("Failed to receive a datagram: {}", err);
                 },
                 Err(err) => {
-                    return Err(NetworkError::FatalError(
-                        format_err!("Reader error: {}", err)
-                    ));
+                    log::error!("Failed to read from socket: {}", err);
+                    return Err(NetworkError::RebuildRequired(self.socket));
                 }
             }
         }
+
+        // More may be waiting; come back once the rest of the server has
+        // had a turn.
+        task::current().notify();
+        Ok(Async::NotReady)
     }
 }
\ No newline at end of file
@@ -0,0 +1,449 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use bytes::Bytes;
+
+use super::packet::{
+    Ack,
+    Header,
+    Packet,
+};
+
+pub const RESEND_TIMEOUT: Duration = Duration::from_millis(100);
+const MAX_RESENDS: u32 = 30;
+const REORDER_WINDOW: u16 = 256;
+const MAX_TRACKED: usize = 64;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Delivery {
+    Unreliable,
+    Reliable,
+    /// Unreliable, but the tag is reported back once the packet is acked.
+    Tracked(u32),
+}
+
+struct PendingMessage {
+    id: u16,
+    payload: Bytes,
+    sent_at: Instant,
+    resends: u32,
+    sequences: Vec<u16>,
+}
+
+pub struct Channel {
+    local_sequence: u16,
+    remote_sequence: u16,
+    received: u32,
+    has_received: bool,
+    ack_pending: bool,
+    next_reliable_id: u16,
+    expected_reliable_id: u16,
+    pending: VecDeque<PendingMessage>,
+    reorder: HashMap<u16, Bytes>,
+    tracked: VecDeque<(u16, u32)>,
+    acknowledged: Vec<u32>,
+}
+
+impl Channel {
+    pub fn new() -> Channel {
+        Channel {
+            local_sequence: 0,
+            remote_sequence: 0,
+            received: 0,
+            has_received: false,
+            ack_pending: false,
+            next_reliable_id: 0,
+            expected_reliable_id: 0,
+            pending: VecDeque::new(),
+            reorder: HashMap::new(),
+            tracked: VecDeque::new(),
+            acknowledged: Vec::new(),
+        }
+    }
+
+    pub fn send(&mut self, delivery: Delivery, payload: Bytes, now: Instant)
+        -> Packet
+    {
+        let reliable_id = match delivery {
+            Delivery::Reliable => {
+                let id = self.next_reliable_id;
+                self.next_reliable_id = id.wrapping_add(1);
+                Some(id)
+            },
+            Delivery::Unreliable | Delivery::Tracked(_) => None,
+        };
+
+        let packet = self.build(reliable_id, Some(payload.clone()));
+
+        if let Delivery::Tracked(tag) = delivery {
+            if self.tracked.len() >= MAX_TRACKED {
+                self.tracked.pop_front();
+            }
+            self.tracked.push_back((packet.header.sequence, tag));
+        }
+
+        if let Some(id) = reliable_id {
+            self.pending.push_back(PendingMessage {
+                id,
+                payload,
+                sent_at: now,
+                resends: 0,
+                sequences: vec![packet.header.sequence],
+            });
+        }
+
+        packet
+    }
+
+    pub fn resend(&mut self, now: Instant) -> Vec<Packet> {
+        let mut packets = Vec::new();
+
+        for index in 0..self.pending.len() {
+            if now.duration_since(self.pending[index].sent_at) < RESEND_TIMEOUT {
+                continue;
+            }
+
+            let id = self.pending[index].id;
+            let payload = self.pending[index].payload.clone();
+            let packet = self.build(Some(id), Some(payload));
+
+            let message = &mut self.pending[index];
+            message.sent_at = now;
+            message.resends += 1;
+            message.sequences.push(packet.header.sequence);
+
+            packets.push(packet);
+        }
+
+        if packets.is_empty() && self.ack_pending {
+            packets.push(self.build(None, None));
+        }
+
+        packets
+    }
+
+    pub fn receive(&mut self, header: &Header, payload: Option<Bytes>)
+        -> Vec<Bytes>
+    {
+        if let Some(ack) = header.ack {
+            self.process_ack(&ack);
+        }
+
+        let mut delivered = Vec::new();
+
+        if !self.record_received(header.sequence) {
+            return delivered;
+        }
+
+        let payload = match payload {
+            Some(payload) => payload,
+            None => return delivered,
+        };
+
+        match header.reliable_id {
+            Some(id) => self.receive_reliable(id, payload, &mut delivered),
+            None => delivered.push(payload),
+        }
+
+        delivered
+    }
+
+    /// Whether a reliable message is still unacknowledged after every
+    /// resend. Later messages can't be delivered past it, so the connection
+    /// has to be given up on.
+    pub fn is_lost(&self, now: Instant) -> bool {
+        self.pending.iter().any(|message| {
+            message.resends >= MAX_RESENDS
+                && now.duration_since(message.sent_at) >= RESEND_TIMEOUT
+        })
+    }
+
+    /// Whether every reliable message sent so far has been acknowledged.
+    pub fn is_drained(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn take_acknowledged(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.acknowledged)
+    }
+
+    fn build(&mut self, reliable_id: Option<u16>, payload: Option<Bytes>)
+        -> Packet
+    {
+        let header = Header {
+            sequence: self.local_sequence,
+            ack: self.ack(),
+            reliable_id,
+        };
+
+        self.local_sequence = self.local_sequence.wrapping_add(1);
+        self.ack_pending = false;
+
+        Packet { header, payload }
+    }
+
+    fn ack(&self) -> Option<Ack> {
+        if !self.has_received {
+            return None;
+        }
+
+        Some(Ack {
+            sequence: self.remote_sequence,
+            bits: self.received,
+        })
+    }
+
+    fn process_ack(&mut self, ack: &Ack) {
+        self.pending.retain(|message| {
+            !message.sequences.iter().any(|sequence| ack.contains(*sequence))
+        });
+
+        let acknowledged = &mut self.acknowledged;
+        self.tracked.retain(|(sequence, tag)| {
+            if ack.contains(*sequence) {
+                acknowledged.push(*tag);
+                return false;
+            }
+            true
+        });
+    }
+
+    fn record_received(&mut self, sequence: u16) -> bool {
+        if !self.has_received {
+            self.has_received = true;
+            self.remote_sequence = sequence;
+            self.received = 0;
+            self.ack_pending = true;
+            return true;
+        }
+
+        if sequence == self.remote_sequence {
+            return false;
+        }
+
+        if sequence_greater_than(sequence, self.remote_sequence) {
+            let shift = sequence.wrapping_sub(self.remote_sequence) as u32;
+            self.received = if shift <= 32 {
+                self.received.checked_shl(shift).unwrap_or(0)
+                    | (1 << (shift - 1))
+            } else {
+                0
+            };
+            self.remote_sequence = sequence;
+            self.ack_pending = true;
+            return true;
+        }
+
+        let distance = self.remote_sequence.wrapping_sub(sequence) as u32;
+        if distance > 32 {
+            return false;
+        }
+
+        let bit = 1 << (distance - 1);
+        if self.received & bit != 0 {
+            return false;
+        }
+
+        self.received |= bit;
+        self.ack_pending = true;
+        true
+    }
+
+    fn receive_reliable(
+        &mut self,
+        id: u16,
+        payload: Bytes,
+        delivered: &mut Vec<Bytes>,
+    ) {
+        if id == self.expected_reliable_id {
+            delivered.push(payload);
+            self.expected_reliable_id = id.wrapping_add(1);
+
+            while let Some(payload) = self.reorder.remove(&self.expected_reliable_id) {
+                delivered.push(payload);
+                self.expected_reliable_id = self.expected_reliable_id.wrapping_add(1);
+            }
+        } else if sequence_greater_than(id, self.expected_reliable_id)
+            && id.wrapping_sub(self.expected_reliable_id) < REORDER_WINDOW
+        {
+            self.reorder.insert(id, payload);
+        }
+    }
+}
+
+fn sequence_greater_than(lhs: u16, rhs: u16) -> bool {
+    ((lhs > rhs) && (lhs - rhs <= 32768))
+        || ((lhs < rhs) && (rhs - lhs > 32768))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(sequence: u16, reliable_id: Option<u16>) -> Header {
+        Header { sequence, ack: None, reliable_id }
+    }
+
+    fn payload(text: &'static str) -> Option<Bytes> {
+        Some(Bytes::from_static(text.as_bytes()))
+    }
+
+    #[test]
+    fn compares_sequences_across_wraparound() {
+        assert!(sequence_greater_than(1, 0));
+        assert!(sequence_greater_than(0, 65535));
+        assert!(sequence_greater_than(10, 65530));
+        assert!(!sequence_greater_than(65530, 10));
+        assert!(!sequence_greater_than(5, 5));
+    }
+
+    #[test]
+    fn ack_history_survives_wraparound() {
+        let mut channel = Channel::new();
+
+        for sequence in (65530..=65535).chain(0..5) {
+            assert_eq!(channel.receive(&header(sequence, None), None).len(), 0);
+        }
+
+        let ack = channel.ack().unwrap();
+        assert_eq!(ack.sequence, 4);
+        assert_eq!(ack.bits, (1 << 10) - 1);
+        for sequence in (65530..=65535).chain(0..5) {
+            assert!(ack.contains(sequence), "sequence {} not acked", sequence);
+        }
+        assert!(!ack.contains(65529));
+        assert!(!ack.contains(5));
+    }
+
+    #[test]
+    fn acks_late_packets_within_the_history() {
+        let mut channel = Channel::new();
+
+        channel.receive(&header(65534, None), None);
+        channel.receive(&header(1, None), None);
+        channel.receive(&header(65535, None), payload("late"));
+
+        let ack = channel.ack().unwrap();
+        assert_eq!(ack.sequence, 1);
+        assert!(ack.contains(65535));
+        assert!(!ack.contains(0));
+        assert!(ack.contains(65534));
+    }
+
+    #[test]
+    fn drops_duplicate_and_stale_packets() {
+        let mut channel = Channel::new();
+
+        assert_eq!(channel.receive(&header(100, None), payload("a")).len(), 1);
+        assert_eq!(channel.receive(&header(100, None), payload("a")).len(), 0);
+
+        assert_eq!(channel.receive(&header(99, None), payload("b")).len(), 1);
+        assert_eq!(channel.receive(&header(99, None), payload("b")).len(), 0);
+
+        assert_eq!(channel.receive(&header(100 - 33, None), payload("c")).len(), 0);
+    }
+
+    #[test]
+    fn delivers_reliable_messages_in_order() {
+        let mut channel = Channel::new();
+
+        assert_eq!(channel.receive(&header(0, Some(1)), payload("b")).len(), 0);
+        assert_eq!(channel.receive(&header(1, Some(2)), payload("c")).len(), 0);
+
+        let delivered = channel.receive(&header(2, Some(0)), payload("a"));
+        assert_eq!(delivered, vec![
+            payload("a").unwrap(),
+            payload("b").unwrap(),
+            payload("c").unwrap(),
+        ]);
+
+        // A resend of a delivered message arrives under a new sequence.
+        assert_eq!(channel.receive(&header(3, Some(1)), payload("b")).len(), 0);
+    }
+
+    #[test]
+    fn reorders_reliable_ids_across_wraparound() {
+        let mut channel = Channel::new();
+        channel.expected_reliable_id = 65535;
+
+        assert_eq!(channel.receive(&header(0, Some(0)), payload("b")).len(), 0);
+
+        let delivered = channel.receive(&header(1, Some(65535)), payload("a"));
+        assert_eq!(delivered, vec![payload("a").unwrap(), payload("b").unwrap()]);
+        assert_eq!(channel.expected_reliable_id, 1);
+    }
+
+    #[test]
+    fn discards_reliable_messages_beyond_the_reorder_window() {
+        let mut channel = Channel::new();
+
+        channel.receive(&header(0, Some(REORDER_WINDOW - 1)), payload("kept"));
+        channel.receive(&header(1, Some(REORDER_WINDOW)), payload("dropped"));
+
+        assert!(channel.reorder.contains_key(&(REORDER_WINDOW - 1)));
+        assert!(!channel.reorder.contains_key(&REORDER_WINDOW));
+    }
+
+    #[test]
+    fn resends_until_acknowledged() {
+        let start = Instant::now();
+        let mut sender = Channel::new();
+        let mut receiver = Channel::new();
+
+        let first = sender.send(Delivery::Reliable, Bytes::from_static(b"hello"), start);
+        assert!(sender.resend(start).is_empty());
+
+        let resent = sender.resend(start + RESEND_TIMEOUT);
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].header.reliable_id, first.header.reliable_id);
+        assert_ne!(resent[0].header.sequence, first.header.sequence);
+
+        // Only the resend makes it; acking it settles the original too.
+        receiver.receive(&resent[0].header, resent[0].payload.clone());
+        let ack = receiver.resend(start + RESEND_TIMEOUT).remove(0);
+        sender.receive(&ack.header, None);
+
+        assert!(sender.pending.is_empty());
+        let later = sender.resend(start + RESEND_TIMEOUT * 2);
+        assert!(later.iter().all(|packet| packet.header.reliable_id.is_none()));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_resend() {
+        let mut now = Instant::now();
+        let mut channel = Channel::new();
+        channel.send(Delivery::Reliable, Bytes::from_static(b"hello"), now);
+
+        for _ in 0..MAX_RESENDS {
+            assert!(!channel.is_lost(now));
+            now += RESEND_TIMEOUT;
+            channel.resend(now);
+        }
+
+        assert!(!channel.is_lost(now));
+        assert!(channel.is_lost(now + RESEND_TIMEOUT));
+    }
+
+    #[test]
+    fn reports_tracked_packets_once_acked() {
+        let now = Instant::now();
+        let mut sender = Channel::new();
+        let mut receiver = Channel::new();
+
+        let packet = sender.send(Delivery::Tracked(7), Bytes::from_static(b"state"), now);
+        sender.send(Delivery::Tracked(8), Bytes::from_static(b"lost"), now);
+        receiver.receive(&packet.header, packet.payload);
+
+        let ack = receiver.resend(now).remove(0);
+        sender.receive(&ack.header, None);
+
+        assert_eq!(sender.take_acknowledged(), vec![7]);
+        assert!(sender.take_acknowledged().is_empty());
+    }
+}
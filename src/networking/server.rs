@@ -1,59 +1,103 @@
+use std::io;
+use std::net;
 use std::sync::{
     Arc,
     Mutex,
 };
+use std::time::{
+    Duration,
+    Instant,
+};
 
 use failure::{
     format_err,
     Error,
 };
+use futures::future;
+use futures::sync::mpsc;
 use tokio::net::{
     UdpSocket,
     UdpFramed,
 };
 use tokio::prelude::*;
-
-use eternalreckoning_core::net::codec::EternalReckoningCodec;
+use tokio::reactor::Handle;
+use tokio::timer::Delay;
 
 use super::{
-    error::NetworkError,
+    error::{
+        BindError,
+        NetworkError,
+    },
+    packet::PacketCodec,
+    ratelimit::RateLimitConfig,
+    session::SessionKey,
+    socket::{
+        self,
+        Endpoint,
+    },
     state::{
         State,
         SharedState,
     },
     reader::{
+        DIRECT_CAPACITY,
+        FeedbackTx,
         Reader,
         Tx,
     },
     writer::{
+        ClosedTx,
         Writer,
         Rx,
     },
 };
 
+const REBIND_ATTEMPTS: u32 = 8;
+const REBIND_BACKOFF: Duration = Duration::from_millis(100);
+const REBIND_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
 pub struct Server {
     state: SharedState,
+    sessions: SessionKey,
+    drain_timeout: Duration,
+    sockets: Vec<(Endpoint, net::UdpSocket)>,
 }
 
 impl Server {
-    pub fn new() -> Server {
+    pub fn new(
+        rate_limit: RateLimitConfig,
+        sessions: SessionKey,
+        drain_timeout: Duration,
+    ) -> Server
+    {
         Server {
-            state: Arc::new(Mutex::new(State::new())),
+            state: Arc::new(Mutex::new(State::new(rate_limit))),
+            sessions,
+            drain_timeout,
+            sockets: Vec::new(),
         }
     }
 
-    pub fn run(
-        self,
-        address: &String,
-        rx: Rx,
-        tx: Tx,
-    )
-    {
-        let addr = address.parse().unwrap();
-        let socket = UdpSocket::bind(&addr).unwrap();
-        log::info!("Listening on: {}", &addr);
+    /// Binds a socket for every address up front, so that failures are
+    /// reported before anything else starts.
+    pub fn bind(&mut self, addresses: &[String]) -> Result<(), BindError> {
+        self.sockets = socket::bind_all(addresses)?;
+
+        for (endpoint, _) in &self.sockets {
+            log::info!("Listening on: {}", endpoint.addr);
+        }
+
+        Ok(())
+    }
 
-        let server = ServerFuture::new(&self.state, socket, tx, rx);
+    /// Serves until `rx` is closed, then disconnects every client. Once the
+    /// disconnects are sent, `closed_tx` is notified.
+    pub fn run(self, rx: Rx, tx: Tx, feedback_tx: FeedbackTx, closed_tx: ClosedTx) {
+        // Sockets have to be registered from within the runtime.
+        let server = future::lazy(move || {
+            ServerFuture::new(self, tx, feedback_tx, closed_tx, rx)
+        })
+            .and_then(|server| server);
 
         tokio::run(
             server
@@ -67,44 +111,150 @@ impl Server {
     }
 }
 
+struct Binding {
+    endpoint: Endpoint,
+    failures: u32,
+    retry: Option<Delay>,
+}
+
+/// Drives a reader per socket and the writer. A lost socket is bound again
+/// on the same address, keeping all client state, with exponential backoff
+/// between attempts.
 struct ServerFuture {
-    reader: Reader,
+    readers: Vec<Reader>,
     writer: Writer,
+    bindings: Vec<Binding>,
 }
 
 impl ServerFuture {
-    pub fn new(state: &SharedState, socket: UdpSocket, tx: Tx, rx: Rx)
-        -> ServerFuture
+    pub fn new(
+        server: Server,
+        tx: Tx,
+        feedback_tx: FeedbackTx,
+        closed_tx: ClosedTx,
+        rx: Rx,
+    ) -> Result<ServerFuture, Error>
     {
-        let framed = UdpFramed::new(socket, EternalReckoningCodec);
-        let (sink, stream) = framed.split();
+        if server.sockets.is_empty() {
+            return Err(format_err!("Server has no bound sockets"));
+        }
 
-        ServerFuture {
-            reader: Reader::new(state.clone(), stream, tx),
-            writer: Writer::new(state.clone(), sink, rx),
+        let (direct_tx, direct_rx) = mpsc::channel(DIRECT_CAPACITY);
+
+        let readers = (0..server.sockets.len())
+            .map(|index| Reader::new(
+                index,
+                server.state.clone(),
+                tx.clone(),
+                feedback_tx.clone(),
+                direct_tx.clone(),
+                server.sessions.clone()
+            ))
+            .collect();
+        let writer = Writer::new(
+            server.state.clone(),
+            server.sockets.len(),
+            rx,
+            direct_rx,
+            feedback_tx,
+            closed_tx,
+            server.drain_timeout
+        );
+
+        let mut future = ServerFuture {
+            readers,
+            writer,
+            bindings: Vec::new(),
+        };
+
+        for (index, (endpoint, socket)) in server.sockets.into_iter().enumerate() {
+            future.attach(index, socket)?;
+            future.bindings.push(Binding { endpoint, failures: 0, retry: None });
         }
+
+        Ok(future)
     }
 
-    fn map_result(result: Poll<(), NetworkError>) -> Poll<(), Error> {
-        match result {
-            Ok(result) => Ok(result),
-            Err(NetworkError::RebuildRequired) => Ok(Async::Ready(())),
-            Err(NetworkError::FatalError(err)) => Err(err),
-        }
+    /// Hands the socket to its reader, and a second handle to it to the
+    /// writer, which sends datagrams directly so that one it can't deliver
+    /// is simply dropped.
+    fn attach(&mut self, index: usize, socket: net::UdpSocket) -> Result<(), io::Error> {
+        let handle = Handle::default();
+        let sender = UdpSocket::from_std(socket.try_clone()?, &handle)?;
+        let receiver = UdpSocket::from_std(socket, &handle)?;
+
+        self.readers[index].attach(UdpFramed::new(receiver, PacketCodec));
+        self.writer.attach(index, sender);
+        Ok(())
     }
 
-    fn join_result(lhs: Poll<(), Error>, rhs: Poll<(), Error>) -> Poll<(), Error> {
-        if lhs.is_err() {
-            return lhs;
+    fn schedule_rebind(&mut self, index: usize) -> Result<(), Error> {
+        let binding = &mut self.bindings[index];
+
+        if binding.failures >= REBIND_ATTEMPTS {
+            return Err(format_err!(
+                "Failed to re-bind {} after {} attempts",
+                binding.endpoint.addr,
+                REBIND_ATTEMPTS
+            ));
         }
-        if rhs.is_err() {
-            return rhs;
+
+        let backoff = (REBIND_BACKOFF * 2u32.pow(binding.failures)).min(REBIND_BACKOFF_MAX);
+        binding.failures += 1;
+        log::warn!(
+            "Re-binding {} in {:?} (attempt {} of {})",
+            binding.endpoint.addr,
+            backoff,
+            binding.failures,
+            REBIND_ATTEMPTS
+        );
+
+        binding.retry = Some(Delay::new(Instant::now() + backoff));
+        Ok(())
+    }
+
+    fn poll_rebind(&mut self, index: usize) -> Result<(), Error> {
+        loop {
+            match self.bindings[index].retry {
+                Some(ref mut retry) => {
+                    if retry.poll()?.is_not_ready() {
+                        return Ok(());
+                    }
+                },
+                None => return Ok(()),
+            }
+            self.bindings[index].retry = None;
+
+            let addr = self.bindings[index].endpoint.addr;
+            let result = self.bindings[index].endpoint.bind()
+                .and_then(|socket| self.attach(index, socket));
+
+            match result {
+                Ok(()) => {
+                    log::info!("Re-bound server socket on {}", addr);
+                    self.bindings[index].failures = 0;
+                    return Ok(());
+                },
+                Err(err) => {
+                    log::warn!("Failed to re-bind {}: {}", addr, err);
+                    self.schedule_rebind(index)?;
+                },
+            }
         }
+    }
 
-        if lhs.unwrap() == Async::Ready(()) || rhs.unwrap() == Async::Ready(()) {
+    fn poll_sockets(&mut self) -> Poll<(), NetworkError> {
+        // The writer only finishes once it has shut the server down.
+        if self.writer.poll()?.is_ready() {
             return Ok(Async::Ready(()));
         }
 
+        for (index, reader) in self.readers.iter_mut().enumerate() {
+            if reader.poll()?.is_ready() {
+                return Err(NetworkError::RebuildRequired(index));
+            }
+        }
+
         Ok(Async::NotReady)
     }
 }
@@ -114,17 +264,22 @@ impl Future for ServerFuture {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<(), Error> {
-        let result = Self::join_result(
-            Self::map_result(self.reader.poll()),
-            Self::map_result(self.writer.poll())
-        );
+        loop {
+            for index in 0..self.bindings.len() {
+                self.poll_rebind(index)?;
+            }
 
-        match result {
-            Ok(Async::NotReady) => Ok(Async::NotReady),
-            Err(err) => Err(err),
-            Ok(Async::Ready(())) => {
-                return Err(format_err!("Server socket unexpectedly lost"));
-            },
+            match self.poll_sockets() {
+                Ok(result) => return Ok(result),
+                Err(NetworkError::FatalError(err)) => return Err(err),
+                Err(NetworkError::RebuildRequired(index)) => {
+                    log::warn!("Server socket on {} lost", self.bindings[index].endpoint.addr);
+                    // Release the old socket so its address can be bound again.
+                    self.readers[index].detach();
+                    self.writer.detach(index);
+                    self.schedule_rebind(index)?;
+                },
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,114 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use super::signing::{
+    sign,
+    Secret,
+};
+
+/// Issues and checks session tokens. A token holds the second it was
+/// issued, counted from when the key was made, and a signature over that
+/// time and the client's uuid. Connected clients are sent a new token
+/// every `refresh_interval`, so a token stays valid for that long past
+/// the session TTL.
+#[derive(Clone)]
+pub struct SessionKey {
+    secret: Secret,
+    created: Instant,
+    ttl: Duration,
+}
+
+impl SessionKey {
+    pub fn new(ttl: Duration) -> SessionKey {
+        SessionKey {
+            secret: rand::random(),
+            created: Instant::now(),
+            ttl,
+        }
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        self.ttl / 2
+    }
+
+    pub fn issue(&self, uuid: &Uuid) -> u64 {
+        self.issue_at(uuid, self.now())
+    }
+
+    pub fn verify(&self, uuid: &Uuid, token: u64) -> bool {
+        self.verify_at(uuid, token, self.now())
+    }
+
+    fn now(&self) -> u32 {
+        self.created.elapsed().as_secs() as u32
+    }
+
+    fn issue_at(&self, uuid: &Uuid, issued: u32) -> u64 {
+        u64::from(issued) << 32 | u64::from(self.signature(uuid, issued))
+    }
+
+    fn verify_at(&self, uuid: &Uuid, token: u64, now: u32) -> bool {
+        let issued = (token >> 32) as u32;
+        let max_age = (self.ttl + self.refresh_interval()).as_secs().max(1);
+        if issued > now || u64::from(now - issued) > max_age {
+            return false;
+        }
+
+        let signature = self.signature(uuid, issued).to_be_bytes();
+        signature.ct_eq(&(token as u32).to_be_bytes()).into()
+    }
+
+    fn signature(&self, uuid: &Uuid, issued: u32) -> u32 {
+        let mut data = [0; 20];
+        data[..16].copy_from_slice(uuid.as_bytes());
+        data[16..].copy_from_slice(&issued.to_be_bytes());
+
+        (sign(&self.secret, &data) >> 32) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SessionKey {
+        SessionKey::new(Duration::from_secs(30))
+    }
+
+    #[test]
+    fn accepts_tokens_within_the_ttl_and_refresh_interval() {
+        let key = key();
+        let uuid = Uuid::new_v4();
+        let token = key.issue_at(&uuid, 100);
+
+        assert!(key.verify_at(&uuid, token, 100));
+        assert!(key.verify_at(&uuid, token, 145));
+        assert!(!key.verify_at(&uuid, token, 146));
+        assert!(!key.verify_at(&uuid, token, 99));
+    }
+
+    #[test]
+    fn rejects_tokens_for_another_client_or_key() {
+        let key = key();
+        let uuid = Uuid::new_v4();
+        let token = key.issue_at(&uuid, 100);
+
+        assert!(!key.verify_at(&Uuid::new_v4(), token, 100));
+        assert!(!SessionKey::new(key.ttl).verify_at(&uuid, token, 100));
+    }
+
+    #[test]
+    fn rejects_a_changed_issue_time() {
+        let key = key();
+        let uuid = Uuid::new_v4();
+        let token = key.issue_at(&uuid, 100);
+
+        let moved = u64::from(200u32) << 32 | token & 0xffff_ffff;
+        assert!(!key.verify_at(&uuid, moved, 200));
+    }
+}
@@ -0,0 +1,19 @@
+use hmac::{
+    Hmac,
+    Mac,
+};
+use sha2::Sha256;
+
+pub type Secret = [u8; 32];
+
+pub fn sign(secret: &Secret, data: &[u8]) -> u64 {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.input(data);
+
+    let code = mac.result().code();
+    let mut signature = [0; 8];
+    signature.copy_from_slice(&code[..8]);
+
+    u64::from_be_bytes(signature)
+}
\ No newline at end of file
@@ -0,0 +1,148 @@
+use std::io;
+use std::net::{
+    SocketAddr,
+    UdpSocket,
+};
+
+use net2::UdpBuilder;
+
+use super::error::BindError;
+
+/// An address the server listens on, kept so that its socket can be bound
+/// again after being lost.
+#[derive(Clone, Copy)]
+pub struct Endpoint {
+    pub addr: SocketAddr,
+    only_v6: bool,
+}
+
+impl Endpoint {
+    pub fn bind(&self) -> io::Result<UdpSocket> {
+        match self.addr {
+            SocketAddr::V4(_) => UdpBuilder::new_v4()?.bind(self.addr),
+            SocketAddr::V6(_) => {
+                let builder = UdpBuilder::new_v6()?;
+                if self.only_v6 {
+                    builder.only_v6(true)?;
+                }
+                builder.bind(self.addr)
+            },
+        }
+    }
+}
+
+/// Whether an error only concerns the datagram or its destination, such as
+/// an unreachable or invalid address, rather than the socket itself.
+pub fn is_datagram_error(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::AddrNotAvailable
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::InvalidInput
+        | io::ErrorKind::PermissionDenied => return true,
+        _ => (),
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(code) = err.raw_os_error() {
+            return [
+                libc::EAFNOSUPPORT,
+                libc::EHOSTDOWN,
+                libc::EHOSTUNREACH,
+                libc::EMSGSIZE,
+                libc::ENETDOWN,
+                libc::ENETUNREACH,
+                libc::ENOBUFS,
+            ].contains(&code);
+        }
+    }
+
+    false
+}
+
+pub fn parse_addresses(addresses: &[String]) -> Result<Vec<SocketAddr>, BindError> {
+    if addresses.is_empty() {
+        return Err(BindError::NoAddress);
+    }
+
+    addresses.iter()
+        .map(|address| {
+            address.parse::<SocketAddr>()
+                .map_err(|err| BindError::InvalidAddress(address.clone(), err))
+        })
+        .collect()
+}
+
+/// Binds every address. When both IPv4 and IPv6 addresses are given, IPv6
+/// sockets are restricted to IPv6 so they don't claim the IPv4 port too.
+pub fn bind_all(addresses: &[String]) -> Result<Vec<(Endpoint, UdpSocket)>, BindError> {
+    let addrs = parse_addresses(addresses)?;
+
+    let dual_stack = addrs.iter().any(|addr| addr.is_ipv4())
+        && addrs.iter().any(|addr| addr.is_ipv6());
+
+    addrs.into_iter()
+        .map(|addr| {
+            let endpoint = Endpoint { addr, only_v6: dual_stack };
+            let socket = endpoint.bind()
+                .map_err(|err| BindError::Bind(addr, err))?;
+
+            // Rebinding must reuse the port picked for port 0.
+            let addr = socket.local_addr().unwrap_or(addr);
+            Ok((Endpoint { addr, ..endpoint }, socket))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_port() -> u16 {
+        UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn binds_ipv4_and_ipv6_on_the_same_port() {
+        let port = free_port();
+        let addresses = vec![format!("0.0.0.0:{}", port), format!("[::]:{}", port)];
+
+        let sockets = bind_all(&addresses).unwrap();
+
+        assert_eq!(sockets.len(), 2);
+        assert!(sockets.iter().all(|(endpoint, _)| endpoint.addr.port() == port));
+    }
+
+    #[test]
+    fn keeps_the_port_picked_for_port_zero() {
+        let sockets = bind_all(&["127.0.0.1:0".to_string()]).unwrap();
+        let (endpoint, socket) = &sockets[0];
+
+        assert_ne!(endpoint.addr.port(), 0);
+        assert_eq!(endpoint.addr, socket.local_addr().unwrap());
+    }
+
+    #[test]
+    fn reports_a_busy_port() {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        match bind_all(&[addr.to_string()]) {
+            Err(BindError::Bind(failed, _)) => assert_eq!(failed, addr),
+            _ => panic!("bound a port that is in use"),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_and_invalid_addresses() {
+        match bind_all(&[]) {
+            Err(BindError::NoAddress) => (),
+            _ => panic!("accepted an empty address list"),
+        }
+        match bind_all(&["localhost".to_string()]) {
+            Err(BindError::InvalidAddress(address, _)) => assert_eq!(address, "localhost"),
+            _ => panic!("accepted an invalid address"),
+        }
+    }
+}
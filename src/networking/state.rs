@@ -1,21 +1,75 @@
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use uuid::Uuid;
 
+use super::challenge::Challenger;
+use super::latency::RttEstimator;
+use super::ratelimit::{
+    RateLimitConfig,
+    RateLimiter,
+};
+use super::reliability::Channel;
+
+/// Where a client is reached: its address and the index of the server
+/// socket it talks to.
+#[derive(Clone, Copy)]
+pub struct Route {
+    pub socket: usize,
+    pub addr: SocketAddr,
+}
+
 pub struct State {
-    pub id_to_addr: HashMap<Uuid, SocketAddr>,
+    pub id_to_route: HashMap<Uuid, Route>,
     pub addr_to_id: HashMap<SocketAddr, Uuid>,
+    pub channels: HashMap<Uuid, Channel>,
+    pub latency: HashMap<Uuid, RttEstimator>,
+    /// Connected addresses that asked for a new challenge, as a client
+    /// restarting its connection does.
+    pub rechallenged: HashSet<SocketAddr>,
+    /// Shared by every socket's reader, so that limits and challenges hold
+    /// no matter which address a client sends to.
+    pub challenger: Challenger,
+    pub limiter: RateLimiter,
+    pub started: Instant,
+    pub closing: bool,
 }
 
 pub type SharedState = Arc<Mutex<State>>;
 
 impl State {
-    pub fn new() -> State {
+    pub fn new(rate_limit: RateLimitConfig) -> State {
         State {
-            id_to_addr: HashMap::new(),
+            id_to_route: HashMap::new(),
             addr_to_id: HashMap::new(),
+            channels: HashMap::new(),
+            latency: HashMap::new(),
+            rechallenged: HashSet::new(),
+            challenger: Challenger::new(),
+            limiter: RateLimiter::new(rate_limit),
+            started: Instant::now(),
+            closing: false,
         }
     }
+
+    /// Forgets everything about a client, returning where it was reached.
+    pub fn remove(&mut self, id: &Uuid) -> Option<Route> {
+        self.channels.remove(id);
+        self.latency.remove(id);
+
+        let route = self.id_to_route.remove(id)?;
+        self.addr_to_id.remove(&route.addr);
+        self.rechallenged.remove(&route.addr);
+        Some(route)
+    }
+
+    /// Milliseconds since the server started, as sent to clients.
+    pub fn server_time(&self, now: Instant) -> u64 {
+        now.duration_since(self.started).as_millis() as u64
+    }
 }
\ No newline at end of file
@@ -1,116 +1,415 @@
+use std::collections::VecDeque;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use bytes::{
+    Bytes,
+    BytesMut,
+};
 use failure::{
     format_err,
     Error,
 };
-use futures::stream::{
-    Stream,
-    SplitSink,
-};
-use futures::sink::Sink;
-use tokio::net::UdpFramed;
+use futures::stream::Stream;
+use tokio::codec::Encoder;
+use tokio::net::UdpSocket;
 use tokio::prelude::{
     Async,
     Future,
     Poll,
 };
+use tokio::timer::{
+    Delay,
+    Interval,
+};
 use uuid::Uuid;
 
 use eternalreckoning_core::net::{
     codec::EternalReckoningCodec,
-    operation::Operation,
+    operation::{
+        self,
+        Operation,
+    },
 };
 
 use super::error::NetworkError;
-use super::state::SharedState;
+use super::feedback::Feedback;
+use super::latency::PING_INTERVAL;
+use super::outbound::Outbound;
+use super::packet::{
+    Packet,
+    PacketCodec,
+};
+use super::reader::{
+    DIRECT_CAPACITY,
+    FeedbackTx,
+};
+use super::reliability::{
+    Delivery,
+    RESEND_TIMEOUT,
+};
+use super::socket;
+use super::state::{
+    Route,
+    SharedState,
+};
+
+pub type Rx = futures::sync::mpsc::UnboundedReceiver<(Uuid, Outbound)>;
+pub type DirectRx = futures::sync::mpsc::Receiver<(Packet, Route)>;
+pub type ClosedTx = std::sync::mpsc::Sender<()>;
 
-pub type Rx = futures::sync::mpsc::UnboundedReceiver<(Uuid, Operation)>;
+const SHUTDOWN_REASON: &str = "Server shutting down";
 
 pub struct Writer {
     shared: SharedState,
-    sink: SplitSink<UdpFramed<EternalReckoningCodec>>,
+    sockets: Vec<Option<UdpSocket>>,
     rx: Rx,
-    state: WriterState,
-}
-
-#[derive(PartialEq)]
-enum WriterState {
-    Idle,
-    Sending,
+    direct_rx: DirectRx,
+    feedback_tx: FeedbackTx,
+    closed_tx: ClosedTx,
+    codec: EternalReckoningCodec,
+    resend: Interval,
+    ping: Interval,
+    queue: VecDeque<(Bytes, Route)>,
+    drain_timeout: Duration,
+    closing: Option<Delay>,
 }
 
 impl Writer {
     pub fn new(
         shared: SharedState,
-        sink: SplitSink<UdpFramed<EternalReckoningCodec>>,
+        sockets: usize,
         rx: Rx,
+        direct_rx: DirectRx,
+        feedback_tx: FeedbackTx,
+        closed_tx: ClosedTx,
+        drain_timeout: Duration,
     ) -> Writer
     {
-        let state = WriterState::Idle;
+        let codec = EternalReckoningCodec;
+        let resend = Interval::new_interval(RESEND_TIMEOUT);
+        let ping = Interval::new_interval(PING_INTERVAL);
+        let queue = VecDeque::new();
 
-        Writer { shared, sink, rx, state }
+        Writer {
+            shared,
+            sockets: (0..sockets).map(|_| None).collect(),
+            rx,
+            direct_rx,
+            feedback_tx,
+            closed_tx,
+            codec,
+            resend,
+            ping,
+            queue,
+            drain_timeout,
+            closing: None,
+        }
+    }
+
+    /// Sets the handle of the given socket to write to, again after a
+    /// rebuild.
+    pub fn attach(&mut self, index: usize, socket: UdpSocket) {
+        self.sockets[index] = Some(socket);
     }
 
-    fn send(&mut self, client: Uuid, op: Operation) -> Result<(), Error> {
-        let shared = self.shared.lock()
+    pub fn detach(&mut self, index: usize) {
+        self.sockets[index] = None;
+    }
+
+    fn send(&mut self, client: Uuid, delivery: Delivery, op: Operation)
+        -> Result<(), Error>
+    {
+        let mut payload = BytesMut::new();
+        self.codec.encode(op, &mut payload)?;
+
+        let mut shared = self.shared.lock()
             .map_err(|err| {
                 format_err!("Failed to access shared state: {}", err)
             })?;
 
-        if let Some(addr) = shared.id_to_addr.get(&client) {
-            self.sink.start_send((op, *addr))?;
+        let route = match shared.id_to_route.get(&client) {
+            Some(route) => *route,
+            None => {
+                log::warn!("Attempted to send to unknown client {}", client);
+                return Ok(());
+            },
+        };
+
+        if let Some(channel) = shared.channels.get_mut(&client) {
+            let packet = channel.send(delivery, payload.freeze(), Instant::now());
+            self.queue.push_back((encode(packet)?, route));
         } else {
-            log::warn!("Attempted to send to unknown client {}", client);
+            log::warn!("Attempted to send to client {} without a channel", client);
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, client: Uuid) -> Result<(), Error> {
+        let mut shared = self.shared.lock()
+            .map_err(|err| {
+                format_err!("Failed to access shared state: {}", err)
+            })?;
+
+        if shared.remove(&client).is_some() {
+            log::debug!("Removed connection to {}", client);
+        }
+
+        Ok(())
+    }
+
+    fn resend(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+
+        let mut shared = self.shared.lock()
+            .map_err(|err| {
+                format_err!("Failed to access shared state: {}", err)
+            })?;
+        let shared = &mut *shared;
+
+        let mut lost = Vec::new();
+        for (id, channel) in shared.channels.iter_mut() {
+            if channel.is_lost(now) {
+                lost.push(*id);
+                continue;
+            }
+
+            if let Some(route) = shared.id_to_route.get(id) {
+                for packet in channel.resend(now) {
+                    self.queue.push_back((encode(packet)?, *route));
+                }
+            }
+        }
+
+        for id in lost {
+            log::warn!("Closing connection to {}: reliable message went unacknowledged", id);
+            shared.remove(&id);
+            if self.feedback_tx.send((id, Feedback::Lost)).is_err() {
+                log::debug!("Dropped feedback for {}: simulation stopped", id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ping(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+
+        let mut shared = self.shared.lock()
+            .map_err(|err| {
+                format_err!("Failed to access shared state: {}", err)
+            })?;
+        let shared = &mut *shared;
+        let server_time = shared.server_time(now);
+
+        for (id, channel) in shared.channels.iter_mut() {
+            let route = match shared.id_to_route.get(id) {
+                Some(route) => *route,
+                None => continue,
+            };
+            let sequence = shared.latency.entry(*id)
+                .or_default()
+                .ping(now);
+
+            let op = Operation::SvPing(
+                operation::SvPing { sequence, server_time }
+            );
+            let mut payload = BytesMut::new();
+            self.codec.encode(op, &mut payload)?;
+
+            let packet = channel.send(Delivery::Unreliable, payload.freeze(), now);
+            self.queue.push_back((encode(packet)?, route));
+        }
+
+        Ok(())
+    }
+
+    /// Stops accepting connections and tells every client the server is
+    /// going away, then reports that on `closed_tx`. The writer finishes
+    /// once they have all acknowledged it, or the drain timeout runs out.
+    fn close(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+
+        let mut shared = self.shared.lock()
+            .map_err(|err| {
+                format_err!("Failed to access shared state: {}", err)
+            })?;
+        let shared = &mut *shared;
+        shared.closing = true;
+
+        log::info!("Disconnecting {} clients", shared.channels.len());
+
+        for (id, channel) in shared.channels.iter_mut() {
+            let route = match shared.id_to_route.get(id) {
+                Some(route) => *route,
+                None => continue,
+            };
+
+            let op = Operation::DisconnectMessage(operation::DisconnectMessage {
+                reason: Some(SHUTDOWN_REASON.to_string()),
+            });
+            let mut payload = BytesMut::new();
+            self.codec.encode(op, &mut payload)?;
+
+            let packet = channel.send(Delivery::Reliable, payload.freeze(), now);
+            self.queue.push_back((encode(packet)?, route));
         }
 
+        self.closing = Some(Delay::new(now + self.drain_timeout));
+
+        // Nobody may be waiting for this, which is fine.
+        self.closed_tx.send(()).ok();
+
         Ok(())
     }
 
-    fn poll_sending(&mut self) -> Poll<(), Error> {
-        futures::try_ready!(self.sink.poll_complete());
+    fn poll_closing(&mut self) -> Poll<(), Error> {
+        if self.closing.is_none() {
+            return Ok(Async::NotReady);
+        }
+
+        let undelivered = {
+            let shared = self.shared.lock()
+                .map_err(|err| {
+                    format_err!("Failed to access shared state: {}", err)
+                })?;
+            shared.channels.values()
+                .filter(|channel| !channel.is_drained())
+                .count()
+        };
+
+        if undelivered == 0 && self.queue.is_empty() {
+            log::info!("All clients disconnected");
+            return Ok(Async::Ready(()));
+        }
 
+        if let Some(ref mut deadline) = self.closing {
+            futures::try_ready!(deadline.poll());
+        }
+
+        log::warn!(
+            "Gave up waiting for {} clients to acknowledge the disconnect",
+            undelivered
+        );
         Ok(Async::Ready(()))
     }
 
-    fn poll_idle(&mut self) -> Poll<(), Error> {
-        match self.rx.poll().map_err(|_| format_err!("Reader disconnected"))? {
-            Async::Ready(Some((client, op))) => {
-                self.send(client, op)?;
-                return Ok(Async::Ready(()));
-            },
-            Async::NotReady => return Ok(Async::NotReady),
-            Async::Ready(None) => {
-                return Err(format_err!("Reader disconnected"));
-            },
+    fn poll_ping(&mut self) -> Result<(), Error> {
+        loop {
+            match self.ping.poll()? {
+                Async::Ready(Some(_)) => self.ping()?,
+                Async::Ready(None) => {
+                    return Err(format_err!("Ping timer stopped"));
+                },
+                Async::NotReady => return Ok(()),
+            }
         }
     }
-}
 
-impl Future for Writer {
-    type Item = ();
-    type Error = NetworkError;
+    fn poll_resend(&mut self) -> Result<(), Error> {
+        loop {
+            match self.resend.poll()? {
+                Async::Ready(Some(_)) => self.resend()?,
+                Async::Ready(None) => {
+                    return Err(format_err!("Resend timer stopped"));
+                },
+                Async::NotReady => return Ok(()),
+            }
+        }
+    }
 
-    fn poll(&mut self) -> Poll<(), NetworkError> {
+    fn poll_outbound(&mut self) -> Result<(), Error> {
         loop {
-            match self.state {
-                WriterState::Sending => {
-                    futures::try_ready!(
-                        self.poll_sending()
-                            .map_err(|err| NetworkError::FatalError(
-                                format_err!("Writer error: {}", err)
-                            ))
-                    );
-                    self.state = WriterState::Idle;
+            match self.rx.poll().map_err(|_| format_err!("Reader disconnected"))? {
+                Async::Ready(Some((client, Outbound::Send(delivery, op)))) => {
+                    self.send(client, delivery, op)?;
+                },
+                Async::Ready(Some((client, Outbound::Remove))) => self.remove(client)?,
+                Async::NotReady => return Ok(()),
+                Async::Ready(None) => return self.close(),
+            }
+        }
+    }
+
+    /// Takes challenges from the readers while there is room to queue them.
+    /// Otherwise they wait in the bounded channel, and once that is full the
+    /// readers drop them.
+    fn poll_direct(&mut self) -> Result<(), Error> {
+        while self.queue.len() < DIRECT_CAPACITY {
+            match self.direct_rx.poll().map_err(|_| format_err!("Reader disconnected"))? {
+                Async::Ready(Some((packet, route))) => {
+                    self.queue.push_back((encode(packet)?, route));
+                },
+                Async::NotReady => return Ok(()),
+                Async::Ready(None) => {
+                    return Err(format_err!("Reader disconnected"));
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_sending(&mut self) -> Result<(), NetworkError> {
+        while let Some((datagram, route)) = self.queue.pop_front() {
+            let socket = match self.sockets.get_mut(route.socket) {
+                Some(Some(socket)) => socket,
+                // Socket is being rebuilt; reliable messages get resent.
+                Some(None) => continue,
+                None => {
+                    log::error!("No socket {} to send to {}", route.socket, route.addr);
+                    continue;
+                },
+            };
+
+            match socket.poll_send_to(&datagram, &route.addr) {
+                Ok(Async::Ready(_)) => (),
+                Ok(Async::NotReady) => {
+                    self.queue.push_front((datagram, route));
+                    return Ok(());
+                },
+                Err(ref err) if socket::is_datagram_error(err) => {
+                    log::warn!("Failed to send to {}: {}", route.addr, err);
                 },
-                WriterState::Idle => {
-                    futures::try_ready!(
-                        self.poll_idle()
-                            .map_err(|err| NetworkError::FatalError(
-                                format_err!("Writer error: {}", err)
-                            ))
-                    );
-                    self.state = WriterState::Sending;
+                Err(err) => {
+                    log::error!("Failed to write to socket {}: {}", route.socket, err);
+                    return Err(NetworkError::RebuildRequired(route.socket));
                 },
             }
         }
+
+        Ok(())
+    }
+}
+
+fn encode(packet: Packet) -> Result<Bytes, Error> {
+    let mut datagram = BytesMut::new();
+    PacketCodec.encode(packet, &mut datagram)?;
+    Ok(datagram.freeze())
+}
+
+impl Future for Writer {
+    type Item = ();
+    type Error = NetworkError;
+
+    fn poll(&mut self) -> Poll<(), NetworkError> {
+        let open = self.closing.is_none();
+
+        self.poll_resend()
+            .and_then(|_| if open { self.poll_ping() } else { Ok(()) })
+            .and_then(|_| self.poll_direct())
+            .and_then(|_| if open { self.poll_outbound() } else { Ok(()) })
+            .map_err(|err| NetworkError::FatalError(
+                format_err!("Writer error: {}", err)
+            ))?;
+
+        self.poll_sending()?;
+
+        self.poll_closing()
+            .map_err(|err| NetworkError::FatalError(
+                format_err!("Writer error: {}", err)
+            ))
     }
 }
\ No newline at end of file
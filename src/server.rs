@@ -1,9 +1,10 @@
 use std::sync::mpsc::{
     channel,
+    sync_channel,
     TryRecvError,
 };
-use std::time::Duration;
 use std::thread;
+use std::time::Duration;
 
 use failure::{
     format_err,
@@ -11,63 +12,200 @@ use failure::{
 };
 use futures::sync::mpsc::unbounded;
 
+use crate::auth::{
+    self,
+    AuthConfig,
+    Authenticator,
+};
 use crate::simulation::build_simulation;
+use crate::simulation::map::Map;
+use crate::simulation::resource::{
+    CollisionConfig,
+    LagCompensationConfig,
+    PersistenceConfig,
+    PhysicsConfig,
+    SpawnSelection,
+    StaticGeometry,
+};
+use crate::simulation::system::{
+    ChatConfig,
+    CombatConfig,
+    Persistence,
+};
 use crate::simulation::Event;
-use crate::networking::Server;
+use crate::networking::{
+    parse_addresses,
+    RateLimitConfig,
+    Server,
+    SessionKey,
+};
+use crate::shutdown;
 use crate::util::config::Config;
 
+/// One address, or a list of them to listen on several at once, such as an
+/// IPv4 and an IPv6 address.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum BindAddress {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl BindAddress {
+    pub fn addresses(&self) -> Vec<String> {
+        match self {
+            BindAddress::One(address) => vec![address.clone()],
+            BindAddress::Many(addresses) => addresses.clone(),
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct ServerConfig {
     pub tick_rate: u64,
-    pub bind_address: String,
+    pub max_catch_up_steps: u32,
+    pub max_events_per_tick: usize,
+    pub inbound_queue_capacity: usize,
+    pub bind_address: BindAddress,
     pub client_ttl_ms: u64,
+    pub session_ttl_ms: u64,
+    pub shutdown_timeout_ms: u64,
+    pub view_radius: f64,
+    pub max_speed: f64,
+    pub jump_speed: f64,
+    pub map_file: Option<String>,
+    pub spawn_selection: SpawnSelection,
+    pub physics: PhysicsConfig,
+    pub collision: CollisionConfig,
+    pub combat: CombatConfig,
+    pub chat: ChatConfig,
+    pub persistence: PersistenceConfig,
+    pub lag_compensation: LagCompensationConfig,
+    pub rate_limit: RateLimitConfig,
+    pub auth: AuthConfig,
 }
 
 impl Default for ServerConfig {
     fn default() -> ServerConfig {
         ServerConfig {
             tick_rate: 60,
-            bind_address: "127.0.0.1:6142".to_string(),
+            max_catch_up_steps: 5,
+            max_events_per_tick: 1024,
+            inbound_queue_capacity: 4096,
+            bind_address: BindAddress::One("127.0.0.1:6142".to_string()),
             client_ttl_ms: 500,
+            session_ttl_ms: 30000,
+            shutdown_timeout_ms: 2000,
+            view_radius: 100.0,
+            max_speed: 10.0,
+            jump_speed: 5.0,
+            map_file: None,
+            spawn_selection: SpawnSelection::RoundRobin,
+            physics: PhysicsConfig::default(),
+            collision: CollisionConfig::default(),
+            combat: CombatConfig::default(),
+            chat: ChatConfig::default(),
+            persistence: PersistenceConfig::default(),
+            lag_compensation: LagCompensationConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            auth: AuthConfig::default(),
         }
     }
 }
 
-pub fn main(config: Config) -> Result<(), Error> {
-    let (outbound_tx, outbound_rx) = unbounded();
-    let (inbound_tx, inbound_rx) = channel();
+/// Loads everything the configuration refers to on disk.
+fn load(config: &ServerConfig)
+    -> Result<(Box<dyn Authenticator>, StaticGeometry, Map), Error>
+{
+    if config.tick_rate == 0 {
+        return Err(format_err!("tick-rate must be greater than zero"));
+    }
 
-    let addr = config.server.bind_address.clone();
-    thread::spawn(move || {
-        let server = Server::new();
-        server.run(&addr, outbound_rx, inbound_tx);
-    });
+    let authenticator = auth::from_config(&config.auth)?;
+    let geometry = StaticGeometry::from_config(&config.collision)?;
+    let map = match config.map_file {
+        Some(ref path) => Map::from_file(path)?,
+        None => Map::empty(),
+    };
+
+    Ok((authenticator, geometry, map))
+}
+
+/// Validates the configuration without binding sockets or starting the
+/// simulation.
+pub fn check(config: &Config) -> Result<(), Error> {
+    config.logging.level.parse::<log::LevelFilter>()
+        .map_err(|err| format_err!("Invalid log level {} ({})", config.logging.level, err))?;
+    load(&config.server)?;
+    parse_addresses(&config.server.bind_address.addresses())?;
 
-    let tick_length = Duration::from_millis(
-        1000 / config.server.tick_rate
+    Ok(())
+}
+
+pub fn main(config: Config) -> Result<(), Error> {
+    let (authenticator, geometry, map) = load(&config.server)?;
+
+    let drain_timeout = Duration::from_millis(config.server.shutdown_timeout_ms);
+    let sessions = SessionKey::new(Duration::from_millis(config.server.session_ttl_ms));
+    let mut server = Server::new(
+        config.server.rate_limit.clone(),
+        sessions.clone(),
+        drain_timeout
     );
+    server.bind(&config.server.bind_address.addresses())?;
+
+    let (outbound_tx, outbound_rx) = unbounded();
+    let (inbound_tx, inbound_rx) = sync_channel(config.server.inbound_queue_capacity.max(1));
+    let (feedback_tx, feedback_rx) = channel();
+    let (closed_tx, closed_rx) = channel();
 
     let mut game = build_simulation(
+        &config.server,
+        geometry,
+        map,
         outbound_tx,
-        config.server.client_ttl_ms
-    );
+        feedback_rx,
+        sessions,
+        authenticator
+    )?;
+
+    let network = thread::spawn(move || {
+        server.run(outbound_rx, inbound_tx, feedback_tx, closed_tx);
+    });
+
+    shutdown::listen()?;
 
-    game.run(
-        move || {
+    // The inbound queue stays open until the network thread has finished, so
+    // that datagrams arriving while the simulation winds down are not
+    // mistaken for a failure.
+    let result = game.run(
+        || {
             match inbound_rx.try_recv() {
                 Ok((uuid, op)) => {
                     Ok(Some(Event { uuid, op }))
                 },
                 Err(TryRecvError::Empty) => Ok(None),
-                Err(TryRecvError::Disconnected) => Err(()),
+                Err(TryRecvError::Disconnected) => {
+                    Err(format_err!("Network thread disconnected"))
+                },
             }
-        },
-        tick_length
-    )
-        .map_err(|_| {
-            format_err!("Network thread disconnected")
-        })?;
+        }
+    );
 
-    Ok(())
+    // Dropping the systems closes the outbound queue, which tells the
+    // network thread to disconnect everyone. Players are saved once the
+    // disconnects are out, while it waits for them to be acknowledged. An
+    // error only means the network thread is already gone.
+    let world = game.stop();
+    closed_rx.recv().ok();
+    Persistence::save_all(&world);
+    drop(world);
+
+    if network.join().is_err() {
+        log::error!("Network thread panicked");
+    }
+    drop(inbound_rx);
+
+    result
 }
\ No newline at end of file
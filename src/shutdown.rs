@@ -0,0 +1,41 @@
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use failure::Error;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Requests a shutdown on SIGINT or SIGTERM. A second signal while the
+/// server is still shutting down exits immediately.
+#[cfg(unix)]
+pub fn listen() -> Result<(), Error> {
+    extern "C" fn handle(signal: libc::c_int) {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            unsafe { libc::_exit(128 + signal) };
+        }
+    }
+
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(*signal, handler) } == libc::SIG_ERR {
+            return Err(failure::format_err!(
+                "Failed to install handler for signal {}",
+                signal
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen() -> Result<(), Error> {
+    log::warn!("Graceful shutdown on signals is not supported on this platform");
+    Ok(())
+}
@@ -0,0 +1,7 @@
+use specs::prelude::*;
+
+pub struct Acceleration(pub nalgebra::Vector3<f64>);
+
+impl Component for Acceleration {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+use specs::prelude::*;
+
+/// The authenticated account a client logged in with, which its saved state
+/// is keyed on.
+pub struct Account(pub String);
+
+impl Component for Account {
+    type Storage = VecStorage<Self>;
+}
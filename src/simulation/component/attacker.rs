@@ -0,0 +1,11 @@
+use std::time::Instant;
+
+use specs::prelude::*;
+
+pub struct Attacker {
+    pub ready_at: Instant,
+}
+
+impl Component for Attacker {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
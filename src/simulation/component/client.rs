@@ -6,6 +6,7 @@ use specs::prelude::*;
 pub enum ClientState {
     Connecting,
     Connected,
+    Dropped,
 }
 
 pub struct Client {
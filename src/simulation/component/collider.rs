@@ -0,0 +1,19 @@
+use specs::prelude::*;
+
+pub struct Collider {
+    pub radius: f64,
+    pub grounded: bool,
+    /// Position resolved on the previous tick, swept from to prevent
+    /// tunneling. Cleared when an entity is teleported.
+    pub previous: Option<nalgebra::Point3<f64>>,
+}
+
+impl Collider {
+    pub fn new(radius: f64) -> Collider {
+        Collider { radius, grounded: false, previous: None }
+    }
+}
+
+impl Component for Collider {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+use std::time::Instant;
+
+use specs::prelude::*;
+
+pub struct Dead {
+    pub until: Instant,
+}
+
+impl Component for Dead {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
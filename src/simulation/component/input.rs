@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+use specs::prelude::*;
+
+use eternalreckoning_core::net::operation::ClInput;
+
+pub struct Input {
+    /// Sequence of the last command that was simulated.
+    pub sequence: Option<u32>,
+    /// Commands waiting for a tick of their own, oldest first.
+    pub pending: VecDeque<ClInput>,
+    pub direction: nalgebra::Vector3<f64>,
+    pub yaw: f64,
+    pub pitch: f64,
+}
+
+impl Input {
+    /// Sequence to report back to the client. Before anything has been
+    /// simulated this is the sequence preceding the first command received,
+    /// so a client counting from 0 doesn't see its first command confirmed
+    /// early.
+    pub fn acknowledged(&self) -> u32 {
+        self.sequence.unwrap_or_else(|| {
+            self.pending.front()
+                .map_or(u32::MAX, |command| command.sequence.wrapping_sub(1))
+        })
+    }
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Input {
+            sequence: None,
+            pending: VecDeque::new(),
+            direction: nalgebra::Vector3::zeros(),
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl Component for Input {
+    type Storage = VecStorage<Self>;
+}
@@ -0,0 +1,14 @@
+use std::time::Duration;
+
+use specs::prelude::*;
+
+#[derive(Default)]
+pub struct Latency {
+    pub rtt: Duration,
+    pub jitter: Duration,
+    pub clock_offset: i64,
+}
+
+impl Component for Latency {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
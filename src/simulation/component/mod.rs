@@ -1,11 +1,37 @@
+mod acceleration;
+mod account;
+mod attacker;
 pub mod client;
+mod collider;
+mod dead;
 mod health;
 mod id;
+mod input;
+mod latency;
 mod name;
+mod orientation;
 mod position;
+mod prop;
+pub mod snapshot;
+mod spawnprotection;
+mod velocity;
+mod zone;
 
+pub use acceleration::Acceleration;
+pub use account::Account;
+pub use attacker::Attacker;
 pub use client::Client;
+pub use collider::Collider;
+pub use dead::Dead;
 pub use health::Health;
 pub use id::Id;
+pub use input::Input;
+pub use latency::Latency;
 pub use name::Name;
-pub use position::Position;
\ No newline at end of file
+pub use orientation::Orientation;
+pub use position::Position;
+pub use prop::Prop;
+pub use snapshot::SnapshotHistory;
+pub use spawnprotection::SpawnProtection;
+pub use velocity::Velocity;
+pub use zone::Zone;
\ No newline at end of file
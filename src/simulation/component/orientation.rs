@@ -0,0 +1,7 @@
+use specs::prelude::*;
+
+pub struct Orientation(pub nalgebra::UnitQuaternion<f64>);
+
+impl Component for Orientation {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+use specs::prelude::*;
+
+pub struct Prop {
+    pub model: String,
+}
+
+impl Component for Prop {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
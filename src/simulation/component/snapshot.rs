@@ -0,0 +1,170 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+    VecDeque,
+};
+
+use specs::prelude::*;
+use uuid::Uuid;
+
+const MAX_UNACKNOWLEDGED: usize = 64;
+
+#[derive(Clone, PartialEq)]
+pub struct EntitySnapshot {
+    pub position: Option<nalgebra::Point3<f64>>,
+    pub health: Option<u64>,
+    pub velocity: Option<nalgebra::Vector3<f64>>,
+    pub orientation: Option<nalgebra::UnitQuaternion<f64>>,
+}
+
+pub type Snapshot = HashMap<Uuid, EntitySnapshot>;
+
+#[derive(Default)]
+pub struct SnapshotHistory {
+    next_id: u32,
+    sent: VecDeque<(u32, Snapshot)>,
+    baseline: Option<Snapshot>,
+    visible: HashSet<Uuid>,
+}
+
+impl Component for SnapshotHistory {
+    type Storage = VecStorage<Self>;
+}
+
+impl SnapshotHistory {
+    pub fn new() -> SnapshotHistory {
+        SnapshotHistory::default()
+    }
+
+    pub fn baseline(&self) -> Option<&Snapshot> {
+        self.baseline.as_ref()
+    }
+
+    /// Snapshots sent since the baseline, oldest first.
+    pub fn unacknowledged(&self) -> impl Iterator<Item = &Snapshot> {
+        self.sent.iter().map(|(_, snapshot)| snapshot)
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        if self.sent.len() >= MAX_UNACKNOWLEDGED {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((id, snapshot));
+
+        id
+    }
+
+    pub fn update_visible(&mut self, visible: HashSet<Uuid>)
+        -> (Vec<Uuid>, Vec<Uuid>)
+    {
+        let entered: Vec<Uuid> = visible.difference(&self.visible)
+            .cloned()
+            .collect();
+        let left: Vec<Uuid> = self.visible.difference(&visible)
+            .cloned()
+            .collect();
+
+        for uuid in &left {
+            self.forget(uuid);
+        }
+        self.visible = visible;
+
+        (entered, left)
+    }
+
+    pub fn acknowledge(&mut self, id: u32) {
+        let index = match self.sent.iter().position(|(sent, _)| *sent == id) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let mut acknowledged = self.sent.drain(..=index);
+        self.baseline = acknowledged.next_back().map(|(_, snapshot)| snapshot);
+    }
+
+    fn forget(&mut self, uuid: &Uuid) {
+        if let Some(baseline) = self.baseline.as_mut() {
+            baseline.remove(uuid);
+        }
+        for (_, snapshot) in self.sent.iter_mut() {
+            snapshot.remove(uuid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(uuid: Uuid, health: u64) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert(uuid, EntitySnapshot {
+            position: None,
+            health: Some(health),
+            velocity: None,
+            orientation: None,
+        });
+        snapshot
+    }
+
+    fn health(snapshot: &Snapshot, uuid: &Uuid) -> Option<u64> {
+        snapshot.get(uuid).and_then(|entity| entity.health)
+    }
+
+    #[test]
+    fn acknowledging_drops_older_snapshots() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        history.push(snapshot(uuid, 1));
+        let id = history.push(snapshot(uuid, 2));
+        history.push(snapshot(uuid, 3));
+        history.acknowledge(id);
+
+        assert_eq!(health(history.baseline().unwrap(), &uuid), Some(2));
+        let pending: Vec<_> = history.unacknowledged()
+            .map(|snapshot| health(snapshot, &uuid))
+            .collect();
+        assert_eq!(pending, vec![Some(3)]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_unacknowledged_snapshot() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        let evicted = history.push(snapshot(uuid, 0));
+        for health in 1..=MAX_UNACKNOWLEDGED as u64 {
+            history.push(snapshot(uuid, health));
+        }
+
+        assert_eq!(history.unacknowledged().count(), MAX_UNACKNOWLEDGED);
+        assert_eq!(health(history.unacknowledged().next().unwrap(), &uuid), Some(1));
+
+        // An ack for an evicted snapshot can't establish a baseline.
+        history.acknowledge(evicted);
+        assert!(history.baseline().is_none());
+        assert_eq!(history.unacknowledged().count(), MAX_UNACKNOWLEDGED);
+    }
+
+    #[test]
+    fn forgets_entities_that_leave_view() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        let mut visible = HashSet::new();
+        visible.insert(uuid);
+        assert_eq!(history.update_visible(visible), (vec![uuid], vec![]));
+
+        let id = history.push(snapshot(uuid, 1));
+        history.acknowledge(id);
+        history.push(snapshot(uuid, 2));
+
+        assert_eq!(history.update_visible(HashSet::new()), (vec![], vec![uuid]));
+        assert!(history.baseline().unwrap().is_empty());
+        assert!(history.unacknowledged().all(|snapshot| snapshot.is_empty()));
+    }
+}
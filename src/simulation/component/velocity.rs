@@ -0,0 +1,7 @@
+use specs::prelude::*;
+
+pub struct Velocity(pub nalgebra::Vector3<f64>);
+
+impl Component for Velocity {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
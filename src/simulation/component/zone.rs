@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+
+use specs::prelude::*;
+
+pub struct Zone {
+    pub name: String,
+    pub min: nalgebra::Point3<f64>,
+    pub max: nalgebra::Point3<f64>,
+    pub properties: HashMap<String, String>,
+}
+
+impl Zone {
+    pub fn contains(&self, point: &nalgebra::Point3<f64>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x
+            && point.y >= self.min.y && point.y <= self.max.y
+            && point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    /// Whether players inside the zone are protected from damage.
+    pub fn is_safe(&self) -> bool {
+        self.properties.get("safe").map(String::as_str) == Some("true")
+    }
+}
+
+impl Component for Zone {
+    type Storage = VecStorage<Self>;
+}
\ No newline at end of file
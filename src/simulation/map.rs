@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+
+use failure::{
+    format_err,
+    Error,
+};
+use nalgebra::{
+    Point3,
+    UnitQuaternion,
+    Vector3,
+};
+use serde::Deserialize;
+use specs::prelude::*;
+use uuid::Uuid;
+
+use super::component::{
+    Id,
+    Orientation,
+    Position,
+    Prop,
+    Zone,
+};
+use super::resource::{
+    EntityIndex,
+    SpawnPoint,
+    SpawnPoints,
+    SpawnSelection,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SpawnPointEntry {
+    position: [f64; 3],
+    #[serde(default)]
+    yaw: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PropEntry {
+    model: String,
+    position: [f64; 3],
+    #[serde(default)]
+    yaw: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ZoneEntry {
+    name: String,
+    min: [f64; 3],
+    max: [f64; 3],
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct MapFile {
+    name: String,
+    spawn_points: Vec<SpawnPointEntry>,
+    props: Vec<PropEntry>,
+    zones: Vec<ZoneEntry>,
+}
+
+pub struct Map {
+    file: MapFile,
+}
+
+impl Map {
+    pub fn empty() -> Map {
+        Map { file: MapFile::default() }
+    }
+
+    pub fn from_file(path: &str) -> Result<Map, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| {
+                format_err!("Failed to read map {}: {}", path, err)
+            })?;
+        let file: MapFile = toml::from_str(&contents)
+            .map_err(|err| {
+                format_err!("Failed to parse map {}: {}", path, err)
+            })?;
+
+        log::info!(
+            "Loaded map \"{}\" from {}: {} spawn points, {} props, {} zones",
+            file.name,
+            path,
+            file.spawn_points.len(),
+            file.props.len(),
+            file.zones.len()
+        );
+
+        Ok(Map { file })
+    }
+
+    pub fn populate(self, world: &mut World, selection: SpawnSelection) {
+        let spawn_points = self.file.spawn_points.iter()
+            .map(|spawn| SpawnPoint {
+                position: point(spawn.position),
+                yaw: spawn.yaw,
+            })
+            .collect();
+        world.insert(SpawnPoints::new(spawn_points, selection));
+
+        for prop in self.file.props {
+            let uuid = Uuid::new_v4();
+            let entity = world.create_entity()
+                .with(Id(uuid))
+                .with(Position(point(prop.position)))
+                .with(Orientation(UnitQuaternion::from_axis_angle(
+                    &Vector3::y_axis(),
+                    prop.yaw
+                )))
+                .with(Prop { model: prop.model })
+                .build();
+            world.write_resource::<EntityIndex>().insert(uuid, entity);
+        }
+
+        for zone in self.file.zones {
+            world.create_entity()
+                .with(Zone {
+                    name: zone.name,
+                    min: point(zone.min),
+                    max: point(zone.max),
+                    properties: zone.properties,
+                })
+                .build();
+        }
+    }
+}
+
+fn point(coords: [f64; 3]) -> Point3<f64> {
+    Point3::new(coords[0], coords[1], coords[2])
+}
\ No newline at end of file
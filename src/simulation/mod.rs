@@ -1,9 +1,13 @@
 pub mod component;
+pub mod map;
+pub mod resource;
 pub mod system;
+mod scheduler;
 mod simulation;
 mod event;
 
 pub use event::Event;
+pub use scheduler::Scheduler;
 pub use simulation::build_simulation;
 
 pub type EventQueue = Vec<Event>;
\ No newline at end of file
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use specs::prelude::*;
+use uuid::Uuid;
+
+use super::super::component::Id;
+
+/// Maps network ids to the entities carrying them. Kept in step with the
+/// `Id` storage by whichever system creates or deletes those entities.
+#[derive(Default)]
+pub struct EntityIndex {
+    entities: HashMap<Uuid, Entity>,
+}
+
+impl EntityIndex {
+    pub fn get(&self, uuid: &Uuid) -> Option<Entity> {
+        self.entities.get(uuid).cloned()
+    }
+
+    pub fn insert(&mut self, uuid: Uuid, entity: Entity) {
+        self.entities.insert(uuid, entity);
+    }
+
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<Entity> {
+        self.entities.remove(uuid)
+    }
+
+    /// Compares the index against the `Id` storage, logging every mismatch.
+    /// Returns whether the two agree.
+    pub fn verify(&self, entities: &Entities, ids: &ReadStorage<Id>) -> bool {
+        let mut consistent = true;
+
+        for (entity, id) in (entities, ids).join() {
+            if self.get(&id.0) != Some(entity) {
+                log::error!("Entity index is missing {} ({:?})", id.0, entity);
+                consistent = false;
+            }
+        }
+
+        for (uuid, entity) in &self.entities {
+            let found = ids.get(*entity).map(|id| id.0 == *uuid) == Some(true);
+            if !entities.is_alive(*entity) || !found {
+                log::error!("Entity index has stale entry {} ({:?})", uuid, entity);
+                consistent = false;
+            }
+        }
+
+        consistent
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use std::fs;
+
+use failure::{
+    format_err,
+    Error,
+};
+use nalgebra::{
+    Point3,
+    Vector3,
+};
+
+use super::Heightmap;
+
+const EPSILON: f64 = 1e-9;
+
+type Cell = (i64, i64);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CollisionConfig {
+    pub mesh_file: Option<String>,
+    pub heightmap_file: Option<String>,
+    /// Ground height wherever the heightmap doesn't reach, so there is
+    /// something to stand on without level geometry. Set it to -inf to let
+    /// players fall.
+    pub floor: f64,
+    pub cell_size: f64,
+    pub player_radius: f64,
+}
+
+impl Default for CollisionConfig {
+    fn default() -> CollisionConfig {
+        CollisionConfig {
+            mesh_file: None,
+            heightmap_file: None,
+            floor: 0.0,
+            cell_size: 4.0,
+            player_radius: 0.5,
+        }
+    }
+}
+
+pub struct Triangle {
+    pub a: Point3<f64>,
+    pub b: Point3<f64>,
+    pub c: Point3<f64>,
+    pub normal: Vector3<f64>,
+}
+
+impl Triangle {
+    pub fn closest_point(&self, p: &Point3<f64>) -> Point3<f64> {
+        let ab = self.b - self.a;
+        let ac = self.c - self.a;
+        let ap = p - self.a;
+
+        let d1 = ab.dot(&ap);
+        let d2 = ac.dot(&ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return self.a;
+        }
+
+        let bp = p - self.b;
+        let d3 = ab.dot(&bp);
+        let d4 = ac.dot(&bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return self.b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return self.a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - self.c;
+        let d5 = ab.dot(&cp);
+        let d6 = ac.dot(&cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return self.c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return self.a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            let bc = self.c - self.b;
+            return self.b + bc * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denom = 1.0 / (va + vb + vc);
+        self.a + ab * (vb * denom) + ac * (vc * denom)
+    }
+
+    /// Fraction along `from -> to` at which the segment crosses the triangle.
+    pub fn intersect(&self, from: &Point3<f64>, to: &Point3<f64>) -> Option<f64> {
+        let direction = to - from;
+        let ab = self.b - self.a;
+        let ac = self.c - self.a;
+
+        let h = direction.cross(&ac);
+        let det = ab.dot(&h);
+        if det.abs() < EPSILON {
+            return None;
+        }
+
+        let inv = 1.0 / det;
+        let s = from - self.a;
+        let u = s.dot(&h) * inv;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&ab);
+        let v = direction.dot(&q) * inv;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(&q) * inv;
+        if !(0.0..=1.0).contains(&t) {
+            return None;
+        }
+
+        Some(t)
+    }
+}
+
+/// Static level colliders: a triangle mesh bucketed into a uniform grid over
+/// the X/Z plane, plus an optional terrain heightmap.
+pub struct StaticGeometry {
+    cell_size: f64,
+    triangles: Vec<Triangle>,
+    cells: HashMap<Cell, Vec<usize>>,
+    heightmap: Option<Heightmap>,
+    floor: Option<f64>,
+}
+
+impl StaticGeometry {
+    pub fn new(cell_size: f64) -> StaticGeometry {
+        StaticGeometry {
+            cell_size,
+            triangles: Vec::new(),
+            cells: HashMap::new(),
+            heightmap: None,
+            floor: None,
+        }
+    }
+
+    pub fn from_config(config: &CollisionConfig) -> Result<StaticGeometry, Error> {
+        if !config.cell_size.is_finite() || config.cell_size <= 0.0 {
+            return Err(format_err!("Invalid collision cell size: {}", config.cell_size));
+        }
+
+        let mut geometry = StaticGeometry::new(config.cell_size);
+        if config.floor.is_finite() {
+            geometry.floor = Some(config.floor);
+        }
+
+        if let Some(ref path) = config.mesh_file {
+            geometry.load_mesh(path)?;
+        }
+        if let Some(ref path) = config.heightmap_file {
+            geometry.heightmap = Some(Heightmap::from_file(path)?);
+        }
+
+        Ok(geometry)
+    }
+
+    pub fn add_triangle(&mut self, a: Point3<f64>, b: Point3<f64>, c: Point3<f64>) {
+        let normal = (b - a).cross(&(c - a));
+        let area = normal.norm();
+        if !area.is_finite() || area < EPSILON {
+            return;
+        }
+
+        let index = self.triangles.len();
+        let min = Point3::new(a.x.min(b.x).min(c.x), 0.0, a.z.min(b.z).min(c.z));
+        let max = Point3::new(a.x.max(b.x).max(c.x), 0.0, a.z.max(b.z).max(c.z));
+        let (min, max) = (self.cell(&min), self.cell(&max));
+
+        for x in min.0..=max.0 {
+            for z in min.1..=max.1 {
+                self.cells.entry((x, z))
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        self.triangles.push(Triangle { a, b, c, normal: normal / area });
+    }
+
+    pub fn heightmap(&self) -> Option<&Heightmap> {
+        self.heightmap.as_ref()
+    }
+
+    /// Height of the ground below the given point, from the heightmap or
+    /// else the floor.
+    pub fn ground_at(&self, x: f64, z: f64) -> Option<f64> {
+        self.heightmap()
+            .and_then(|map| map.height_at(x, z))
+            .or(self.floor)
+    }
+
+    /// Triangles whose grid cells overlap the given bounds.
+    pub fn query(&self, min: &Point3<f64>, max: &Point3<f64>) -> Vec<&Triangle> {
+        let (min, max) = (self.cell(min), self.cell(max));
+
+        let mut found: Vec<usize> = Vec::new();
+        for x in min.0..=max.0 {
+            for z in min.1..=max.1 {
+                if let Some(indices) = self.cells.get(&(x, z)) {
+                    found.extend(indices);
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+
+        found.into_iter()
+            .map(|index| &self.triangles[index])
+            .collect()
+    }
+
+    /// Nearest mesh hit along `from -> to` as a fraction and surface normal.
+    pub fn raycast(&self, from: &Point3<f64>, to: &Point3<f64>)
+        -> Option<(f64, Vector3<f64>)>
+    {
+        let min = Point3::new(from.x.min(to.x), 0.0, from.z.min(to.z));
+        let max = Point3::new(from.x.max(to.x), 0.0, from.z.max(to.z));
+
+        self.query(&min, &max)
+            .into_iter()
+            .filter_map(|triangle| {
+                triangle.intersect(from, to)
+                    .map(|t| (t, triangle.normal))
+            })
+            .min_by(|lhs, rhs| {
+                lhs.0.partial_cmp(&rhs.0).unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    fn load_mesh(&mut self, path: &str) -> Result<(), Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| {
+                format_err!("Failed to read mesh {}: {}", path, err)
+            })?;
+
+        self.add_mesh(path, &contents)
+    }
+
+    fn add_mesh(&mut self, path: &str, contents: &str) -> Result<(), Error> {
+        let mut vertices: Vec<Point3<f64>> = Vec::new();
+        let before = self.triangles.len();
+
+        for (number, line) in contents.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    let coords: Result<Vec<f64>, _> = tokens.take(3)
+                        .map(|token| token.parse::<f64>())
+                        .collect();
+                    match coords {
+                        Ok(ref coords) if coords.len() == 3 => {
+                            vertices.push(Point3::new(coords[0], coords[1], coords[2]));
+                        },
+                        _ => return Err(format_err!(
+                            "Invalid vertex in {} on line {}",
+                            path,
+                            number + 1
+                        )),
+                    }
+                },
+                Some("f") => {
+                    let face: Option<Vec<Point3<f64>>> = tokens
+                        .map(|token| {
+                            let index = token.split('/').next()?.parse::<i64>().ok()?;
+                            let index = if index < 0 {
+                                vertices.len() as i64 + index
+                            } else {
+                                index - 1
+                            };
+                            vertices.get(index as usize).cloned()
+                        })
+                        .collect();
+                    let face = match face {
+                        Some(ref face) if face.len() >= 3 => face,
+                        _ => return Err(format_err!(
+                            "Invalid face in {} on line {}",
+                            path,
+                            number + 1
+                        )),
+                    };
+
+                    for index in 1..face.len() - 1 {
+                        self.add_triangle(face[0], face[index], face[index + 1]);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        log::info!(
+            "Loaded {} collision triangles from {}",
+            self.triangles.len() - before,
+            path
+        );
+
+        Ok(())
+    }
+
+    fn cell(&self, position: &Point3<f64>) -> Cell {
+        (
+            (position.x / self.cell_size).floor() as i64,
+            (position.z / self.cell_size).floor() as i64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Triangle {
+        Triangle {
+            a: Point3::new(0.0, 0.0, 0.0),
+            b: Point3::new(2.0, 0.0, 0.0),
+            c: Point3::new(0.0, 0.0, 2.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    fn assert_near(lhs: Point3<f64>, rhs: Point3<f64>) {
+        assert!((lhs - rhs).norm() < 1e-6, "{} != {}", lhs, rhs);
+    }
+
+    #[test]
+    fn closest_point_projects_onto_the_face() {
+        let point = triangle().closest_point(&Point3::new(0.5, 3.0, 0.5));
+        assert_near(point, Point3::new(0.5, 0.0, 0.5));
+    }
+
+    #[test]
+    fn closest_point_clamps_to_the_edges() {
+        let triangle = triangle();
+
+        assert_near(
+            triangle.closest_point(&Point3::new(1.0, 1.0, -1.0)),
+            Point3::new(1.0, 0.0, 0.0),
+        );
+        assert_near(
+            triangle.closest_point(&Point3::new(-1.0, 1.0, 1.0)),
+            Point3::new(0.0, 0.0, 1.0),
+        );
+        assert_near(
+            triangle.closest_point(&Point3::new(2.0, 0.0, 2.0)),
+            Point3::new(1.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn closest_point_clamps_to_the_vertices() {
+        let triangle = triangle();
+
+        assert_near(triangle.closest_point(&Point3::new(-1.0, 0.0, -1.0)), triangle.a);
+        assert_near(triangle.closest_point(&Point3::new(4.0, 1.0, -1.0)), triangle.b);
+        assert_near(triangle.closest_point(&Point3::new(-1.0, -1.0, 4.0)), triangle.c);
+    }
+
+    #[test]
+    fn intersect_finds_the_crossing_fraction() {
+        let triangle = triangle();
+        let hit = triangle.intersect(&Point3::new(0.5, 1.0, 0.5), &Point3::new(0.5, -3.0, 0.5));
+
+        assert!((hit.unwrap() - 0.25).abs() < 1e-9);
+        assert!(triangle.intersect(&Point3::new(0.5, 1.0, 0.5), &Point3::new(0.5, 0.5, 0.5)).is_none());
+        assert!(triangle.intersect(&Point3::new(3.0, 1.0, 3.0), &Point3::new(3.0, -1.0, 3.0)).is_none());
+    }
+
+    #[test]
+    fn intersect_ignores_grazing_sweeps() {
+        let triangle = triangle();
+
+        assert!(triangle.intersect(&Point3::new(-1.0, 0.0, 0.5), &Point3::new(3.0, 0.0, 0.5)).is_none());
+        assert!(triangle.intersect(&Point3::new(-1.0, 1e-12, 0.5), &Point3::new(3.0, -1e-12, 0.5)).is_none());
+    }
+
+    #[test]
+    fn raycast_returns_the_nearest_hit() {
+        let mut geometry = StaticGeometry::new(1.0);
+        geometry.add_mesh("test.obj", "\
+            v 0 0 0\n\
+            v 4 0 0\n\
+            v 0 0 4\n\
+            v 0 2 0\n\
+            v 4 2 0\n\
+            v 0 2 4\n\
+            f 1 2 3\n\
+            f 4/1 5/1 6/1\n\
+        ").unwrap();
+
+        let (t, normal) = geometry
+            .raycast(&Point3::new(1.0, 3.0, 1.0), &Point3::new(1.0, -1.0, 1.0))
+            .unwrap();
+        assert!((t - 0.25).abs() < 1e-9);
+        assert!((normal.y.abs() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ground_falls_back_to_the_floor() {
+        let mut geometry = StaticGeometry::new(1.0);
+        assert_eq!(geometry.ground_at(3.0, 4.0), None);
+
+        geometry.floor = Some(-2.0);
+        assert_eq!(geometry.ground_at(3.0, 4.0), Some(-2.0));
+    }
+
+    #[test]
+    fn rejects_malformed_obj_lines() {
+        let meshes = [
+            "v 0 0\n",
+            "v 0 zero 0\n",
+            "v 0 0 0\nv 1 0 0\nf 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 2 4\n",
+            "v 0 0 0\nv 1 0 0\nv 0 0 1\nf 1 x 3\n",
+        ];
+
+        for mesh in meshes.iter() {
+            let mut geometry = StaticGeometry::new(1.0);
+            assert!(geometry.add_mesh("test.obj", mesh).is_err(), "accepted {:?}", mesh);
+        }
+    }
+
+    #[test]
+    fn fans_polygons_and_resolves_negative_indices() {
+        let mut geometry = StaticGeometry::new(1.0);
+        geometry.add_mesh("test.obj", "\
+            # quad\n\
+            v 0 0 0\n\
+            v 1 0 0\n\
+            v 1 0 1\n\
+            v 0 0 1\n\
+            f -4 -3 -2 -1\n\
+        ").unwrap();
+
+        assert_eq!(geometry.triangles.len(), 2);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs;
+
+use failure::{
+    format_err,
+    Error,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct HeightmapFile {
+    origin: [f64; 3],
+    spacing: f64,
+    columns: usize,
+    heights: Vec<f64>,
+}
+
+/// Regular grid of heights over the X/Z plane, with rows running along Z.
+pub struct Heightmap {
+    origin: nalgebra::Point3<f64>,
+    spacing: f64,
+    columns: usize,
+    rows: usize,
+    heights: Vec<f64>,
+}
+
+impl Heightmap {
+    pub fn from_file(path: &str) -> Result<Heightmap, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| {
+                format_err!("Failed to read heightmap {}: {}", path, err)
+            })?;
+        let file: HeightmapFile = toml::from_str(&contents)
+            .map_err(|err| {
+                format_err!("Failed to parse heightmap {}: {}", path, err)
+            })?;
+
+        let rows = match file.columns {
+            0 => 0,
+            columns => file.heights.len() / columns,
+        };
+        if file.columns < 2 || rows < 2 || rows * file.columns != file.heights.len() {
+            return Err(format_err!(
+                "Heightmap {} must be at least 2x2 with whole rows",
+                path
+            ));
+        }
+        if !file.spacing.is_finite() || file.spacing <= 0.0 {
+            return Err(format_err!("Heightmap {} has invalid spacing", path));
+        }
+
+        log::info!("Loaded {}x{} heightmap from {}", file.columns, rows, path);
+
+        Ok(Heightmap {
+            origin: nalgebra::Point3::new(file.origin[0], file.origin[1], file.origin[2]),
+            spacing: file.spacing,
+            columns: file.columns,
+            rows,
+            heights: file.heights,
+        })
+    }
+
+    /// Terrain height below the given point, or `None` outside the map.
+    pub fn height_at(&self, x: f64, z: f64) -> Option<f64> {
+        let u = (x - self.origin.x) / self.spacing;
+        let v = (z - self.origin.z) / self.spacing;
+
+        let max_u = (self.columns - 1) as f64;
+        let max_v = (self.rows - 1) as f64;
+        if !u.is_finite() || !v.is_finite()
+            || u < 0.0 || v < 0.0 || u > max_u || v > max_v
+        {
+            return None;
+        }
+
+        let column = (u.floor() as usize).min(self.columns - 2);
+        let row = (v.floor() as usize).min(self.rows - 2);
+        let fu = u - column as f64;
+        let fv = v - row as f64;
+
+        let h00 = self.height(column, row);
+        let h10 = self.height(column + 1, row);
+        let h01 = self.height(column, row + 1);
+        let h11 = self.height(column + 1, row + 1);
+
+        let near = h00 + (h10 - h00) * fu;
+        let far = h01 + (h11 - h01) * fu;
+
+        Some(self.origin.y + near + (far - near) * fv)
+    }
+
+    fn height(&self, column: usize, row: usize) -> f64 {
+        self.heights[row * self.columns + column]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heightmap() -> Heightmap {
+        Heightmap {
+            origin: nalgebra::Point3::new(10.0, 1.0, 20.0),
+            spacing: 2.0,
+            columns: 3,
+            rows: 2,
+            heights: vec![
+                0.0, 2.0, 4.0,
+                4.0, 6.0, 8.0,
+            ],
+        }
+    }
+
+    #[test]
+    fn height_at_samples_the_grid_points() {
+        let map = heightmap();
+
+        assert_eq!(map.height_at(10.0, 20.0), Some(1.0));
+        assert_eq!(map.height_at(14.0, 20.0), Some(5.0));
+        assert_eq!(map.height_at(14.0, 22.0), Some(9.0));
+    }
+
+    #[test]
+    fn height_at_interpolates_between_samples() {
+        let map = heightmap();
+
+        assert_eq!(map.height_at(11.0, 20.0), Some(2.0));
+        assert_eq!(map.height_at(10.0, 21.0), Some(3.0));
+        assert_eq!(map.height_at(13.0, 21.0), Some(6.0));
+    }
+
+    #[test]
+    fn height_at_is_none_outside_the_map() {
+        let map = heightmap();
+
+        assert_eq!(map.height_at(9.9, 21.0), None);
+        assert_eq!(map.height_at(14.1, 21.0), None);
+        assert_eq!(map.height_at(12.0, 22.1), None);
+        assert_eq!(map.height_at(f64::NAN, 21.0), None);
+    }
+}
@@ -0,0 +1,37 @@
+mod entityindex;
+mod geometry;
+mod heightmap;
+mod physics;
+mod playerstore;
+mod positionhistory;
+mod spatialgrid;
+mod spawnpoints;
+mod tick;
+
+pub use entityindex::EntityIndex;
+pub use geometry::{
+    CollisionConfig,
+    StaticGeometry,
+    Triangle,
+};
+pub use heightmap::Heightmap;
+pub use physics::PhysicsConfig;
+pub use playerstore::{
+    PersistenceConfig,
+    PlayerRecord,
+    PlayerStore,
+};
+pub use positionhistory::{
+    LagCompensationConfig,
+    PositionHistory,
+};
+pub use spatialgrid::SpatialGrid;
+pub use spawnpoints::{
+    SpawnPoint,
+    SpawnPoints,
+    SpawnSelection,
+};
+pub use tick::{
+    Tick,
+    TickStats,
+};
\ No newline at end of file
@@ -0,0 +1,19 @@
+use nalgebra::Vector3;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PhysicsConfig {
+    pub gravity: Vector3<f64>,
+    pub drag: f64,
+    pub max_fall_speed: f64,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> PhysicsConfig {
+        PhysicsConfig {
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            drag: 0.0,
+            max_fall_speed: 50.0,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fs::{
+    self,
+    File,
+    OpenOptions,
+};
+use std::io::{
+    self,
+    BufRead,
+    BufReader,
+    BufWriter,
+    ErrorKind,
+    Write,
+};
+use std::sync::mpsc::{
+    self,
+    Sender,
+};
+use std::thread::{
+    self,
+    JoinHandle,
+};
+
+use failure::{
+    format_err,
+    Error,
+};
+use nalgebra::Point3;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PersistenceConfig {
+    /// Only used with an auth backend that has accounts, such as
+    /// static-file.
+    pub file: Option<String>,
+    pub save_interval_ms: u64,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> PersistenceConfig {
+        PersistenceConfig {
+            file: Some("players.log".to_string()),
+            save_interval_ms: 30000,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct PlayerRecord {
+    pub position: Point3<f64>,
+    pub health: u64,
+}
+
+/// Last known state of every player, keyed by the account they
+/// authenticated with. Changes are appended to a log file, one record per
+/// line, and the log is compacted down to the latest records whenever it is
+/// opened.
+pub struct PlayerStore {
+    records: HashMap<String, PlayerRecord>,
+    log: Option<LogWriter>,
+}
+
+impl PlayerStore {
+    /// A store that keeps records in memory only.
+    pub fn in_memory() -> PlayerStore {
+        PlayerStore {
+            records: HashMap::new(),
+            log: None,
+        }
+    }
+
+    pub fn from_config(config: &PersistenceConfig) -> Result<PlayerStore, Error> {
+        match config.file {
+            Some(ref path) => PlayerStore::open(path),
+            None => Ok(PlayerStore::in_memory()),
+        }
+    }
+
+    pub fn open(path: &str) -> Result<PlayerStore, Error> {
+        let mut records = HashMap::new();
+
+        match File::open(path) {
+            Ok(file) => {
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.map_err(|err| {
+                        format_err!("Failed to read player log {}: {}", path, err)
+                    })?;
+                    match decode(&line) {
+                        Some((name, record)) => {
+                            records.insert(name, record);
+                        },
+                        None => {
+                            log::warn!("Skipping malformed record at {}:{}", path, number + 1);
+                        },
+                    }
+                }
+            },
+            Err(ref err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => {
+                return Err(format_err!("Failed to open player log {}: {}", path, err));
+            },
+        }
+
+        let compacted = format!("{}.tmp", path);
+        {
+            let mut file = File::create(&compacted)
+                .map_err(|err| format_err!("Failed to create {}: {}", compacted, err))?;
+            for (name, record) in &records {
+                file.write_all(encode(name, record).as_bytes())?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&compacted, path)
+            .map_err(|err| format_err!("Failed to replace player log {}: {}", path, err))?;
+
+        let log = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|err| format_err!("Failed to open player log {}: {}", path, err))?;
+        let log = LogWriter::spawn(path, log)?;
+
+        log::info!("Loaded {} player records from {}", records.len(), path);
+
+        Ok(PlayerStore {
+            records,
+            log: Some(log),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlayerRecord> {
+        self.records.get(name)
+    }
+
+    /// Records the state of a player, appending it to the log if it changed.
+    pub fn save(&mut self, name: &str, record: PlayerRecord) -> Result<(), Error> {
+        if self.records.get(name) == Some(&record) {
+            return Ok(());
+        }
+
+        if let Some(ref log) = self.log {
+            log.send(LogEntry::Record(encode(name, &record)))?;
+        }
+        self.records.insert(name.to_string(), record);
+
+        Ok(())
+    }
+
+    /// Asks for everything saved so far to be synced to disk. This happens
+    /// in the background; failures are logged there.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(ref log) = self.log {
+            log.send(LogEntry::Sync)?;
+        }
+        Ok(())
+    }
+}
+
+enum LogEntry {
+    Record(String),
+    Sync,
+}
+
+/// Writes to the player log on a thread of its own, so that a slow disk
+/// never holds up a tick. Dropping it waits for pending writes to finish.
+struct LogWriter {
+    tx: Option<Sender<LogEntry>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LogWriter {
+    fn spawn(path: &str, file: File) -> Result<LogWriter, Error> {
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_string();
+
+        let thread = thread::Builder::new()
+            .name("player-log".to_string())
+            .spawn(move || {
+                let mut file = BufWriter::new(file);
+                let mut dirty = false;
+
+                for entry in rx {
+                    let result = match entry {
+                        LogEntry::Record(line) => {
+                            dirty = true;
+                            file.write_all(line.as_bytes())
+                        },
+                        LogEntry::Sync if dirty => {
+                            dirty = false;
+                            sync(&mut file)
+                        },
+                        LogEntry::Sync => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        log::error!("Failed to write player log {}: {}", path, err);
+                    }
+                }
+
+                if let Err(err) = sync(&mut file) {
+                    log::error!("Failed to write player log {}: {}", path, err);
+                }
+            })
+            .map_err(|err| format_err!("Failed to start player log thread: {}", err))?;
+
+        Ok(LogWriter {
+            tx: Some(tx),
+            thread: Some(thread),
+        })
+    }
+
+    fn send(&self, entry: LogEntry) -> Result<(), Error> {
+        self.tx.as_ref()
+            .and_then(|tx| tx.send(entry).ok())
+            .ok_or_else(|| format_err!("Player log thread stopped"))
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        self.tx.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Player log thread panicked");
+            }
+        }
+    }
+}
+
+fn sync(file: &mut BufWriter<File>) -> io::Result<()> {
+    file.flush()?;
+    file.get_ref().sync_data()
+}
+
+fn encode(name: &str, record: &PlayerRecord) -> String {
+    format!(
+        "{} {} {} {} {}\n",
+        hex::encode(name),
+        record.position.x,
+        record.position.y,
+        record.position.z,
+        record.health
+    )
+}
+
+fn decode(line: &str) -> Option<(String, PlayerRecord)> {
+    let mut fields = line.split_whitespace();
+
+    let name = String::from_utf8(hex::decode(fields.next()?).ok()?).ok()?;
+    let x = fields.next()?.parse::<f64>().ok()?;
+    let y = fields.next()?.parse::<f64>().ok()?;
+    let z = fields.next()?.parse::<f64>().ok()?;
+    let health = fields.next()?.parse::<u64>().ok()?;
+
+    if fields.next().is_some() || !(x.is_finite() && y.is_finite() && z.is_finite()) {
+        return None;
+    }
+
+    Some((name, PlayerRecord { position: Point3::new(x, y, z), health }))
+}
@@ -0,0 +1,209 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use nalgebra::Point3;
+use specs::Entity;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LagCompensationConfig {
+    pub max_rewind_ms: u64,
+    pub interpolation_delay_ms: u64,
+}
+
+impl Default for LagCompensationConfig {
+    fn default() -> LagCompensationConfig {
+        LagCompensationConfig {
+            max_rewind_ms: 250,
+            interpolation_delay_ms: 100,
+        }
+    }
+}
+
+/// Recent positions of every entity, one sample per tick, used to rewind
+/// hit detection to what a lagged client was looking at.
+pub struct PositionHistory {
+    max_rewind: Duration,
+    interpolation_delay: Duration,
+    ticks: VecDeque<(u64, Instant)>,
+    samples: HashMap<Entity, VecDeque<(u64, Point3<f64>)>>,
+}
+
+impl PositionHistory {
+    pub fn new(config: &LagCompensationConfig) -> PositionHistory {
+        PositionHistory {
+            max_rewind: Duration::from_millis(config.max_rewind_ms),
+            interpolation_delay: Duration::from_millis(config.interpolation_delay_ms),
+            ticks: VecDeque::new(),
+            samples: HashMap::new(),
+        }
+    }
+
+    pub fn record<I>(&mut self, tick: u64, now: Instant, positions: I)
+    where
+        I: IntoIterator<Item = (Entity, Point3<f64>)>,
+    {
+        self.ticks.push_back((tick, now));
+        while let Some((_, time)) = self.ticks.front() {
+            if now.duration_since(*time) <= self.max_rewind {
+                break;
+            }
+            self.ticks.pop_front();
+        }
+        let oldest = self.ticks.front().map_or(tick, |(tick, _)| *tick);
+
+        for (entity, position) in positions {
+            let samples = self.samples.entry(entity).or_default();
+            samples.push_back((tick, position));
+            while let Some((sampled, _)) = samples.front() {
+                if *sampled >= oldest {
+                    break;
+                }
+                samples.pop_front();
+            }
+        }
+
+        self.samples.retain(|_, samples| {
+            samples.back().map(|(last, _)| *last) == Some(tick)
+        });
+    }
+
+    /// Point in time a client with the given round trip was seeing when it
+    /// acted, limited to the rewind window.
+    pub fn view_time(&self, now: Instant, rtt: Duration) -> Instant {
+        let rewind = (rtt / 2 + self.interpolation_delay).min(self.max_rewind);
+        now.checked_sub(rewind).unwrap_or(now)
+    }
+
+    /// Position of an entity at the given time, interpolated between the
+    /// surrounding ticks and clamped to the recorded history.
+    pub fn position_at(&self, entity: Entity, time: Instant) -> Option<Point3<f64>> {
+        let samples = self.samples.get(&entity)?;
+
+        let mut before: Option<(Instant, Point3<f64>)> = None;
+        for (tick, position) in samples {
+            let sampled_at = match self.time_of(*tick) {
+                Some(sampled_at) => sampled_at,
+                None => continue,
+            };
+
+            if sampled_at >= time {
+                return Some(match before {
+                    Some((previous_at, previous)) => {
+                        let span = (sampled_at - previous_at).as_secs_f64();
+                        let t = if span > 0.0 {
+                            (time - previous_at).as_secs_f64() / span
+                        } else {
+                            1.0
+                        };
+                        previous + (position - previous) * t
+                    },
+                    None => *position,
+                });
+            }
+
+            before = Some((sampled_at, *position));
+        }
+
+        before.map(|(_, position)| position)
+    }
+
+    fn time_of(&self, tick: u64) -> Option<Instant> {
+        let (first, _) = self.ticks.front()?;
+        let index = tick.checked_sub(*first)? as usize;
+        self.ticks.get(index).map(|(_, time)| *time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{
+        Builder,
+        World,
+        WorldExt,
+    };
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(50);
+
+    fn history(max_rewind_ms: u64) -> PositionHistory {
+        PositionHistory::new(&LagCompensationConfig {
+            max_rewind_ms,
+            interpolation_delay_ms: 100,
+        })
+    }
+
+    /// Records `ticks` ticks in which the entity moves 10 units along X.
+    fn walk(history: &mut PositionHistory, entity: Entity, ticks: u64) -> Instant {
+        let start = Instant::now();
+        for tick in 0..ticks {
+            let position = Point3::new(tick as f64 * 10.0, 0.0, 0.0);
+            history.record(tick, start + TICK * tick as u32, vec![(entity, position)]);
+        }
+        start
+    }
+
+    fn x_at(history: &PositionHistory, entity: Entity, time: Instant) -> f64 {
+        history.position_at(entity, time).unwrap().x
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let entity = World::new().create_entity().build();
+        let mut history = history(1000);
+        let start = walk(&mut history, entity, 4);
+
+        assert!((x_at(&history, entity, start + TICK) - 10.0).abs() < 1e-9);
+        assert!((x_at(&history, entity, start + TICK * 3 / 2) - 15.0).abs() < 1e-6);
+        assert!((x_at(&history, entity, start + TICK * 11 / 4) - 27.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clamps_to_the_recorded_history() {
+        let mut world = World::new();
+        let (entity, unknown) = (world.create_entity().build(), world.create_entity().build());
+        let mut history = history(100);
+        let start = walk(&mut history, entity, 5);
+
+        // Only the ticks within max-rewind of the latest one are kept.
+        assert_eq!(x_at(&history, entity, start), 20.0);
+        assert_eq!(x_at(&history, entity, start + TICK * 10), 40.0);
+        assert!(history.position_at(unknown, start).is_none());
+    }
+
+    #[test]
+    fn forgets_entities_missing_from_the_latest_tick() {
+        let mut world = World::new();
+        let (moved, removed) = (world.create_entity().build(), world.create_entity().build());
+        let mut history = history(1000);
+        let start = Instant::now();
+
+        history.record(0, start, vec![(moved, Point3::origin()), (removed, Point3::origin())]);
+        history.record(1, start + TICK, vec![(moved, Point3::origin())]);
+
+        assert!(history.position_at(moved, start).is_some());
+        assert!(history.position_at(removed, start).is_none());
+    }
+
+    #[test]
+    fn view_time_rewinds_half_the_round_trip_plus_the_delay() {
+        let history = history(250);
+        let now = Instant::now() + Duration::from_secs(10);
+
+        assert_eq!(
+            history.view_time(now, Duration::from_millis(100)),
+            now - Duration::from_millis(150)
+        );
+        assert_eq!(
+            history.view_time(now, Duration::from_secs(1)),
+            now - Duration::from_millis(250)
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use nalgebra::Point3;
+use specs::Entity;
+
+type Cell = (i64, i64, i64);
+
+pub struct SpatialGrid {
+    cell_size: f64,
+    cells: HashMap<Cell, Vec<(Entity, Point3<f64>)>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f64) -> SpatialGrid {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Point3<f64>) {
+        let cell = self.cell(&position);
+        self.cells.entry(cell)
+            .or_default()
+            .push((entity, position));
+    }
+
+    pub fn query(&self, center: &Point3<f64>, radius: f64) -> Vec<Entity> {
+        let min = self.cell(&(center - nalgebra::Vector3::repeat(radius)));
+        let max = self.cell(&(center + nalgebra::Vector3::repeat(radius)));
+        let radius_squared = radius * radius;
+
+        let mut found = Vec::new();
+
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    let entities = match self.cells.get(&(x, y, z)) {
+                        Some(entities) => entities,
+                        None => continue,
+                    };
+
+                    for (entity, position) in entities {
+                        if nalgebra::distance_squared(center, position) <= radius_squared {
+                            found.push(*entity);
+                        }
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    fn cell(&self, position: &Point3<f64>) -> Cell {
+        (
+            (position.x / self.cell_size).floor() as i64,
+            (position.y / self.cell_size).floor() as i64,
+            (position.z / self.cell_size).floor() as i64,
+        )
+    }
+}
\ No newline at end of file
@@ -0,0 +1,136 @@
+use nalgebra::Point3;
+use rand::Rng;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+const CROWD_RADIUS: f64 = 10.0;
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpawnSelection {
+    RoundRobin,
+    Random,
+    LeastCrowded,
+}
+
+#[derive(Copy, Clone)]
+pub struct SpawnPoint {
+    pub position: Point3<f64>,
+    pub yaw: f64,
+}
+
+pub struct SpawnPoints {
+    points: Vec<SpawnPoint>,
+    selection: SpawnSelection,
+    next: usize,
+}
+
+impl SpawnPoints {
+    pub fn new(mut points: Vec<SpawnPoint>, selection: SpawnSelection) -> SpawnPoints {
+        if points.is_empty() {
+            points.push(SpawnPoint { position: Point3::origin(), yaw: 0.0 });
+        }
+
+        SpawnPoints { points, selection, next: 0 }
+    }
+
+    /// Picks a spawn point, taking the positions of live players into
+    /// account for the least-crowded strategy.
+    pub fn select(&mut self, players: &[Point3<f64>]) -> SpawnPoint {
+        match self.selection {
+            SpawnSelection::RoundRobin => {
+                let point = self.points[self.next % self.points.len()];
+                self.next = (self.next + 1) % self.points.len();
+                point
+            },
+            SpawnSelection::Random => {
+                let index = rand::thread_rng().gen_range(0, self.points.len());
+                self.points[index]
+            },
+            SpawnSelection::LeastCrowded => {
+                let crowding = |point: &SpawnPoint| {
+                    let nearby = players.iter()
+                        .filter(|player| {
+                            nalgebra::distance(&point.position, player) <= CROWD_RADIUS
+                        })
+                        .count();
+                    let nearest = players.iter()
+                        .map(|player| nalgebra::distance(&point.position, player))
+                        .fold(f64::INFINITY, f64::min);
+                    (nearby, -nearest)
+                };
+
+                *self.points.iter()
+                    .min_by(|lhs, rhs| {
+                        crowding(lhs).partial_cmp(&crowding(rhs))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .unwrap_or(&self.points[0])
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(selection: SpawnSelection) -> SpawnPoints {
+        SpawnPoints::new(
+            vec![
+                SpawnPoint { position: Point3::new(0.0, 0.0, 0.0), yaw: 0.0 },
+                SpawnPoint { position: Point3::new(100.0, 0.0, 0.0), yaw: 1.0 },
+                SpawnPoint { position: Point3::new(200.0, 0.0, 0.0), yaw: 2.0 },
+            ],
+            selection
+        )
+    }
+
+    #[test]
+    fn round_robin_cycles_through_the_points() {
+        let mut points = points(SpawnSelection::RoundRobin);
+
+        let yaws: Vec<f64> = (0..4).map(|_| points.select(&[]).yaw).collect();
+        assert_eq!(yaws, vec![0.0, 1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn random_picks_every_point_eventually() {
+        let mut points = points(SpawnSelection::Random);
+
+        let mut seen = [false; 3];
+        for _ in 0..1000 {
+            seen[points.select(&[]).yaw as usize] = true;
+        }
+        assert_eq!(seen, [true; 3]);
+    }
+
+    #[test]
+    fn least_crowded_avoids_nearby_players() {
+        let mut points = points(SpawnSelection::LeastCrowded);
+        let players = [
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(101.0, 0.0, 0.0),
+        ];
+
+        assert_eq!(points.select(&players).yaw, 2.0);
+    }
+
+    #[test]
+    fn least_crowded_prefers_the_point_farthest_from_players() {
+        let mut points = points(SpawnSelection::LeastCrowded);
+        let players = [Point3::new(150.0, 0.0, 0.0)];
+
+        assert_eq!(points.select(&players).yaw, 0.0);
+    }
+
+    #[test]
+    fn falls_back_to_the_origin_without_points() {
+        let mut points = SpawnPoints::new(Vec::new(), SpawnSelection::RoundRobin);
+
+        assert_eq!(points.select(&[]).position, Point3::origin());
+    }
+}
@@ -0,0 +1,9 @@
+#[derive(Default)]
+pub struct Tick(pub u64);
+
+#[derive(Default)]
+pub struct TickStats {
+    pub overruns: u64,
+    pub skipped: u64,
+    pub capped: u64,
+}
\ No newline at end of file
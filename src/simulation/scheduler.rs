@@ -0,0 +1,185 @@
+use std::thread;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use failure::Error;
+use specs::{
+    Dispatcher,
+    Entities,
+    Read,
+    ReadStorage,
+    World,
+    WorldExt,
+};
+
+use eternalreckoning_core::simulation::TickTime;
+
+use crate::shutdown;
+
+use super::component::Id;
+use super::resource::{
+    EntityIndex,
+    Tick,
+    TickStats,
+};
+use super::{
+    Event,
+    EventQueue,
+};
+
+/// Runs the dispatcher at a fixed rate. When a tick runs late the following
+/// ticks are run back to back to catch up, up to `max_steps` at a time;
+/// anything further behind is skipped. Each tick takes at most `max_events`
+/// network events, leaving the rest queued for the next one. Runs until a
+/// shutdown is requested.
+pub struct Scheduler<'a, 'b> {
+    dispatcher: Dispatcher<'a, 'b>,
+    world: World,
+    tick_length: Duration,
+    max_steps: u32,
+    max_events: usize,
+}
+
+impl<'a, 'b> Scheduler<'a, 'b> {
+    pub fn new(
+        dispatcher: Dispatcher<'a, 'b>,
+        mut world: World,
+        tick_length: Duration,
+        max_steps: u32,
+        max_events: usize,
+    ) -> Scheduler<'a, 'b>
+    {
+        world.insert(EventQueue::new());
+        world.insert(Tick::default());
+        world.insert(TickStats::default());
+        world.insert(TickTime(Instant::now()));
+
+        Scheduler {
+            dispatcher,
+            world,
+            tick_length,
+            max_steps: max_steps.max(1),
+            max_events: max_events.max(1),
+        }
+    }
+
+    /// Drops the systems, closing their queues to the network, and hands
+    /// back the world.
+    pub fn stop(self) -> World {
+        self.world
+    }
+
+    pub fn run<F>(&mut self, mut events: F) -> Result<(), Error>
+    where
+        F: FnMut() -> Result<Option<Event>, Error>,
+    {
+        self.dispatcher.setup(&mut self.world);
+
+        let mut next = Instant::now();
+
+        loop {
+            if shutdown::requested() {
+                log::info!("Stopping simulation");
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now < next {
+                thread::sleep(next - now);
+                continue;
+            }
+
+            let mut steps = 0;
+            while Instant::now() >= next && steps < self.max_steps {
+                self.step(next, &mut events)?;
+                next += self.tick_length;
+                steps += 1;
+            }
+
+            let now = Instant::now();
+            if now >= next {
+                let behind = now - next;
+                let skipped = (behind.as_nanos() / self.tick_length.as_nanos()) as u64 + 1;
+                next += self.tick_length * skipped as u32;
+
+                let mut stats = self.world.write_resource::<TickStats>();
+                stats.skipped += skipped;
+                log::warn!(
+                    "Simulation fell {:?} behind, skipped {} ticks ({} total)",
+                    behind,
+                    skipped,
+                    stats.skipped
+                );
+            }
+        }
+    }
+
+    fn step<F>(&mut self, scheduled: Instant, events: &mut F) -> Result<(), Error>
+    where
+        F: FnMut() -> Result<Option<Event>, Error>,
+    {
+        let started = Instant::now();
+
+        let capped = {
+            let mut queue = self.world.write_resource::<EventQueue>();
+            while queue.len() < self.max_events {
+                match events()? {
+                    Some(event) => queue.push(event),
+                    None => break,
+                }
+            }
+            queue.len() >= self.max_events
+        };
+        if capped {
+            let mut stats = self.world.write_resource::<TickStats>();
+            stats.capped += 1;
+            log::debug!(
+                "Event limit of {} reached, deferring the rest ({} capped ticks)",
+                self.max_events,
+                stats.capped
+            );
+        }
+
+        self.world.write_resource::<Tick>().0 += 1;
+        self.world.insert(TickTime(scheduled));
+
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+        self.world.write_resource::<EventQueue>().clear();
+
+        #[cfg(debug_assertions)]
+        self.check_index();
+
+        let elapsed = started.elapsed();
+        if elapsed > self.tick_length {
+            let tick = self.world.read_resource::<Tick>().0;
+            let mut stats = self.world.write_resource::<TickStats>();
+            stats.overruns += 1;
+            log::warn!(
+                "Tick {} took {:?}, budget is {:?} ({} overruns)",
+                tick,
+                elapsed,
+                self.tick_length,
+                stats.overruns
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_index(&self) {
+        let (entities, index, ids) = self.world.system_data::<(
+            Entities,
+            Read<EntityIndex>,
+            ReadStorage<Id>,
+        )>();
+
+        debug_assert!(
+            index.verify(&entities, &ids),
+            "Entity index is out of sync with the Id storage"
+        );
+    }
+}
\ No newline at end of file
@@ -1,5 +1,8 @@
+use std::sync::mpsc::Receiver;
 use std::time::Duration;
 
+use failure::Error;
+
 use futures::sync::mpsc::UnboundedSender;
 use specs::{
     DispatcherBuilder,
@@ -8,40 +11,178 @@ use specs::{
 };
 use uuid::Uuid;
 
-use eternalreckoning_core::net::operation::Operation;
+use crate::auth::{
+    AuthWorker,
+    Authenticator,
+};
+use crate::networking::{
+    Feedback,
+    Outbound,
+    SessionKey,
+};
+use crate::server::ServerConfig;
 
-use super::Event;
+use super::Scheduler;
+use super::map::Map;
 use super::component::{
+    Acceleration,
+    Account,
+    Attacker,
     Client,
+    Collider,
+    Dead,
     Health,
+    Id,
+    Input,
+    Latency,
     Name,
+    Orientation,
     Position,
+    Prop,
+    SnapshotHistory,
+    SpawnProtection,
+    Velocity,
+    Zone,
+};
+use super::resource::{
+    EntityIndex,
+    PlayerStore,
+    PositionHistory,
+    SpatialGrid,
+    StaticGeometry,
 };
 use super::system::{
+    Chat,
+    Collision,
+    Combat,
     Connections,
+    FeedbackReceiver,
+    Persistence,
+    Physics,
     PlayerMovement,
+    PositionRecorder,
+    Respawn,
+    SpatialIndexer,
     UpdateSender,
 };
 
-use eternalreckoning_core::simulation::Simulation;
-
 pub fn build_simulation<'a, 'b>(
-    net_tx: UnboundedSender<(Uuid, Operation)>,
-    client_ttl_ms: u64,
-) -> Simulation<'a, 'b, Event>
+    config: &ServerConfig,
+    geometry: StaticGeometry,
+    map: Map,
+    net_tx: UnboundedSender<(Uuid, Outbound)>,
+    feedback: Receiver<(Uuid, Feedback)>,
+    sessions: SessionKey,
+    authenticator: Box<dyn Authenticator>,
+) -> Result<Scheduler<'a, 'b>, Error>
 {
+    let tick_length = Duration::from_nanos(1_000_000_000 / config.tick_rate.max(1));
+
     let mut world = World::new();
 
+    world.register::<Acceleration>();
+    world.register::<Account>();
+    world.register::<Attacker>();
     world.register::<Client>();
+    world.register::<Collider>();
+    world.register::<Dead>();
     world.register::<Health>();
+    world.register::<Id>();
+    world.register::<Input>();
+    world.register::<Latency>();
     world.register::<Name>();
+    world.register::<Orientation>();
     world.register::<Position>();
+    world.register::<Prop>();
+    world.register::<SnapshotHistory>();
+    world.register::<SpawnProtection>();
+    world.register::<Velocity>();
+    world.register::<Zone>();
+
+    world.insert(EntityIndex::default());
+    world.insert(SpatialGrid::new(config.view_radius));
+    world.insert(config.physics.clone());
+    world.insert(geometry);
+    world.insert(PositionHistory::new(&config.lag_compensation));
+    if authenticator.has_accounts() {
+        world.insert(PlayerStore::from_config(&config.persistence)?);
+    } else {
+        log::info!("Not saving players: the auth backend has no accounts");
+        world.insert(PlayerStore::in_memory());
+    }
+
+    map.populate(&mut world, config.spawn_selection);
+
+    // Players walk at up to max-speed, and move vertically no faster than
+    // they jump or fall.
+    let max_player_speed = config.max_speed
+        .hypot(config.jump_speed.max(config.physics.max_fall_speed));
     
     let dispatcher = DispatcherBuilder::new()
-        .with(Connections::new(Duration::from_millis(client_ttl_ms)), "connections", &[])
-        .with(PlayerMovement, "player_movement", &[])
-        .with(UpdateSender::new(net_tx), "update_sender", &["player_movement"])
+        .with(FeedbackReceiver::new(feedback), "feedback_receiver", &[])
+        .with(
+            Connections::new(
+                Duration::from_millis(config.client_ttl_ms),
+                Duration::from_millis(config.session_ttl_ms),
+                config.collision.player_radius,
+                config.combat.max_health,
+                AuthWorker::spawn(authenticator)?,
+                net_tx.clone()
+            ),
+            "connections",
+            &[]
+        )
+        .with(
+            Chat::new(config.chat.clone(), net_tx.clone()),
+            "chat",
+            &["connections"]
+        )
+        .with(
+            PlayerMovement::new(config.max_speed, config.jump_speed),
+            "player_movement",
+            &[]
+        )
+        .with(Physics::new(), "physics", &["player_movement"])
+        .with(Collision, "collision", &["physics"])
+        .with(SpatialIndexer, "spatial_indexer", &["collision"])
+        .with(PositionRecorder, "position_recorder", &["collision"])
+        .with(
+            Combat::new(
+                config.combat.clone(),
+                config.view_radius,
+                max_player_speed,
+                Duration::from_millis(config.lag_compensation.max_rewind_ms),
+                net_tx.clone()
+            ),
+            "combat",
+            &["spatial_indexer", "position_recorder", "feedback_receiver"]
+        )
+        .with(
+            Respawn::new(
+                Duration::from_millis(config.combat.respawn_delay_ms),
+                Duration::from_millis(config.combat.spawn_protection_ms),
+                config.combat.max_health
+            ),
+            "respawn",
+            &["combat"]
+        )
+        .with(
+            UpdateSender::new(net_tx, sessions, config.view_radius),
+            "update_sender",
+            &["respawn"]
+        )
+        .with(
+            Persistence::new(Duration::from_millis(config.persistence.save_interval_ms)),
+            "persistence",
+            &["respawn"]
+        )
         .build();
 
-    Simulation::new(dispatcher, world)
+    Ok(Scheduler::new(
+        dispatcher,
+        world,
+        tick_length,
+        config.max_catch_up_steps,
+        config.max_events_per_tick
+    ))
 }
\ No newline at end of file
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use futures::sync::mpsc::UnboundedSender;
+use specs::prelude::*;
+use uuid::Uuid;
+
+use eternalreckoning_core::net::operation::{
+    self,
+    ChatChannel,
+    Operation,
+};
+use eternalreckoning_core::simulation::TickTime;
+
+use crate::networking::{
+    Delivery,
+    Outbound,
+};
+
+use super::super::{
+    component::{
+        client::ClientState,
+        Client,
+        Id,
+        Name,
+        Position,
+    },
+    resource::EntityIndex,
+    EventQueue,
+};
+
+const SERVER_NAME: &str = "server";
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ChatConfig {
+    pub max_length: usize,
+    pub proximity_radius: f64,
+    pub rate: f64,
+    pub burst: f64,
+    pub blocklist: Vec<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> ChatConfig {
+        ChatConfig {
+            max_length: 256,
+            proximity_radius: 30.0,
+            rate: 1.0,
+            burst: 5.0,
+            blocklist: Vec::new(),
+        }
+    }
+}
+
+struct ChatLimit {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct Chat {
+    config: ChatConfig,
+    blocklist: Vec<String>,
+    limits: HashMap<Entity, ChatLimit>,
+    sender: UnboundedSender<(Uuid, Outbound)>,
+}
+
+impl Chat {
+    pub fn new(config: ChatConfig, sender: UnboundedSender<(Uuid, Outbound)>) -> Chat {
+        let blocklist = config.blocklist.iter()
+            .map(|word| word.trim().to_ascii_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        Chat {
+            config,
+            blocklist,
+            limits: HashMap::new(),
+            sender,
+        }
+    }
+
+    fn allow(&mut self, entity: Entity, now: Instant) -> bool {
+        let burst = self.config.burst;
+        let limit = self.limits.entry(entity)
+            .or_insert(ChatLimit { tokens: burst, updated: now });
+
+        let elapsed = now.duration_since(limit.updated).as_secs_f64();
+        limit.tokens = (limit.tokens + elapsed * self.config.rate).min(burst);
+        limit.updated = now;
+
+        if limit.tokens < 1.0 {
+            return false;
+        }
+        limit.tokens -= 1.0;
+        true
+    }
+
+    /// Masks every blocklisted word, matching whole words only and
+    /// case-insensitively.
+    fn filter(&self, message: &str) -> String {
+        let lowercase = message.to_ascii_lowercase();
+        let mut masked = vec![false; message.len()];
+
+        for word in &self.blocklist {
+            for (start, _) in lowercase.match_indices(word.as_str()) {
+                let end = start + word.len();
+                let before = lowercase[..start].chars().next_back();
+                let after = lowercase[end..].chars().next();
+                if before.is_some_and(char::is_alphanumeric)
+                    || after.is_some_and(char::is_alphanumeric)
+                {
+                    continue;
+                }
+
+                for flag in &mut masked[start..end] {
+                    *flag = true;
+                }
+            }
+        }
+
+        message.char_indices()
+            .map(|(index, c)| if masked[index] { '*' } else { c })
+            .collect()
+    }
+
+    fn send(&self, uuid: Uuid, op: Operation) {
+        self.sender.unbounded_send((uuid, Outbound::Send(Delivery::Reliable, op)))
+            .unwrap_or_else(|err| {
+                log::error!("Failed to send chat message: {}", err);
+            });
+    }
+
+    fn notify(&self, uuid: Uuid, channel: ChatChannel, message: &str) {
+        self.send(uuid, Operation::SvChat(operation::SvChat {
+            sender: None,
+            name: SERVER_NAME.to_string(),
+            channel,
+            message: message.to_string(),
+        }));
+    }
+}
+
+impl<'a> System<'a> for Chat {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, TickTime>,
+        Read<'a, EventQueue>,
+        Read<'a, EntityIndex>,
+        ReadStorage<'a, Id>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, tick_time, events, index, ids, clients, names, positions) = data;
+
+        self.limits.retain(|entity, _| entities.is_alive(*entity));
+
+        for event in &*events {
+            let chat = match event.op {
+                Operation::ClChat(ref chat) => chat,
+                _ => continue,
+            };
+
+            let sender = match index.get(&event.uuid) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            let name = match names.get(sender) {
+                Some(name) => name.0.clone(),
+                None => continue,
+            };
+
+            let message = chat.message.trim();
+            if message.is_empty() {
+                continue;
+            }
+            if message.chars().count() > self.config.max_length {
+                self.notify(
+                    event.uuid,
+                    chat.channel.clone(),
+                    &format!("Message is longer than {} characters", self.config.max_length)
+                );
+                continue;
+            }
+            if !self.allow(sender, tick_time.0) {
+                self.notify(event.uuid, chat.channel.clone(), "You are sending messages too quickly");
+                continue;
+            }
+
+            let message = self.filter(message);
+            let connected = (&ids, &clients).join()
+                .filter(|(_, client)| matches!(client.state, ClientState::Connected));
+
+            let recipients: Vec<Uuid> = match chat.channel {
+                ChatChannel::Global => connected
+                    .map(|(id, _)| id.0)
+                    .collect(),
+                ChatChannel::Proximity => {
+                    let origin = match positions.get(sender) {
+                        Some(pos) => pos.0,
+                        None => continue,
+                    };
+                    let radius = self.config.proximity_radius;
+
+                    connected
+                        .filter(|(id, _)| {
+                            index.get(&id.0)
+                                .and_then(|entity| positions.get(entity))
+                                .map(|pos| nalgebra::distance(&origin, &pos.0) <= radius)
+                                == Some(true)
+                        })
+                        .map(|(id, _)| id.0)
+                        .collect()
+                },
+                ChatChannel::Whisper(ref target) => {
+                    let found = connected
+                        .map(|(id, _)| id.0)
+                        .find(|uuid| {
+                            index.get(uuid)
+                                .and_then(|entity| names.get(entity))
+                                .map(|name| name.0 == *target)
+                                == Some(true)
+                        });
+
+                    match found {
+                        Some(uuid) if uuid == event.uuid => vec![uuid],
+                        Some(uuid) => vec![uuid, event.uuid],
+                        None => {
+                            self.notify(
+                                event.uuid,
+                                chat.channel.clone(),
+                                &format!("No player named {} is online", target)
+                            );
+                            continue;
+                        },
+                    }
+                },
+            };
+
+            log::debug!(
+                "Chat from {} ({:?}) to {} recipients",
+                name,
+                chat.channel,
+                recipients.len()
+            );
+
+            for uuid in recipients {
+                self.send(uuid, Operation::SvChat(operation::SvChat {
+                    sender: Some(event.uuid),
+                    name: name.clone(),
+                    channel: chat.channel.clone(),
+                    message: message.clone(),
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc::unbounded;
+
+    use super::*;
+
+    fn chat(blocklist: &[&str]) -> Chat {
+        let config = ChatConfig {
+            blocklist: blocklist.iter().map(|word| word.to_string()).collect(),
+            ..ChatConfig::default()
+        };
+        Chat::new(config, unbounded().0)
+    }
+
+    #[test]
+    fn masks_whole_words_only() {
+        let chat = chat(&["ass"]);
+
+        assert_eq!(chat.filter("Ass, you ass!"), "***, you ***!");
+        assert_eq!(chat.filter("class assistant bass"), "class assistant bass");
+    }
+
+    #[test]
+    fn masks_phrases() {
+        let chat = chat(&["bad word"]);
+
+        assert_eq!(chat.filter("a Bad Word here"), "a ******** here");
+        assert_eq!(chat.filter("a bad wordsmith"), "a bad wordsmith");
+    }
+}
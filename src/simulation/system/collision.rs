@@ -0,0 +1,126 @@
+use nalgebra::{
+    Point3,
+    Vector3,
+};
+use specs::prelude::*;
+
+use super::super::{
+    component::{
+        Collider,
+        Position,
+        Velocity,
+    },
+    resource::StaticGeometry,
+};
+
+const ITERATIONS: usize = 4;
+const GROUND_SLOPE: f64 = 0.7;
+
+pub struct Collision;
+
+impl Collision {
+    fn sweep(
+        geometry: &StaticGeometry,
+        collider: &Collider,
+        previous: &Point3<f64>,
+        pos: &mut Point3<f64>,
+        vel: &mut Option<&mut Velocity>,
+    ) {
+        let travel = *pos - previous;
+        let distance = travel.norm();
+        if !distance.is_finite() || distance <= collider.radius {
+            return;
+        }
+
+        if let Some((t, normal)) = geometry.raycast(previous, pos) {
+            let stop = (distance * t - collider.radius).max(0.0);
+            *pos = previous + travel * (stop / distance);
+            Self::block(&normal, vel);
+        }
+    }
+
+    fn resolve(
+        geometry: &StaticGeometry,
+        collider: &mut Collider,
+        pos: &mut Point3<f64>,
+        vel: &mut Option<&mut Velocity>,
+    ) {
+        let extent = Vector3::repeat(collider.radius);
+
+        for _ in 0..ITERATIONS {
+            let mut pushed = false;
+
+            for triangle in geometry.query(&(*pos - extent), &(*pos + extent)) {
+                let offset = *pos - triangle.closest_point(pos);
+                let distance = offset.norm();
+                if distance >= collider.radius {
+                    continue;
+                }
+
+                let normal = if distance > 0.0 {
+                    offset / distance
+                } else {
+                    triangle.normal
+                };
+
+                *pos += normal * (collider.radius - distance);
+                Self::block(&normal, vel);
+                if normal.y > GROUND_SLOPE {
+                    collider.grounded = true;
+                }
+                pushed = true;
+            }
+
+            if !pushed {
+                break;
+            }
+        }
+
+        if let Some(height) = geometry.ground_at(pos.x, pos.z) {
+            if pos.y - collider.radius < height {
+                pos.y = height + collider.radius;
+                Self::block(&Vector3::y(), vel);
+                collider.grounded = true;
+            }
+        }
+    }
+
+    fn block(normal: &Vector3<f64>, vel: &mut Option<&mut Velocity>) {
+        if let Some(vel) = vel {
+            let into = vel.0.dot(normal);
+            if into < 0.0 {
+                vel.0 -= normal * into;
+            }
+        }
+    }
+}
+
+impl<'a> System<'a> for Collision {
+    type SystemData = (
+        ReadExpect<'a, StaticGeometry>,
+        WriteStorage<'a, Collider>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (geometry, mut colliders, mut positions, mut velocities) = data;
+
+        let components = (
+            &mut colliders,
+            &mut positions,
+            (&mut velocities).maybe(),
+        );
+
+        for (collider, pos, mut vel) in components.join() {
+            collider.grounded = false;
+
+            if let Some(previous) = collider.previous {
+                Self::sweep(&geometry, collider, &previous, &mut pos.0, &mut vel);
+            }
+            Self::resolve(&geometry, collider, &mut pos.0, &mut vel);
+
+            collider.previous = Some(pos.0);
+        }
+    }
+}
\ No newline at end of file
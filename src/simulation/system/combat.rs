@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use futures::sync::mpsc::UnboundedSender;
+use nalgebra::{
+    Point3,
+    Vector3,
+};
+use specs::prelude::*;
+use uuid::Uuid;
+
+use eternalreckoning_core::net::operation::{
+    self,
+    AttackKind,
+    Operation,
+};
+use eternalreckoning_core::simulation::TickTime;
+
+use crate::networking::{
+    Delivery,
+    Outbound,
+};
+
+use super::super::{
+    component::{
+        client::ClientState,
+        Attacker,
+        Client,
+        Collider,
+        Health,
+        Id,
+        Latency,
+        Position,
+        SpawnProtection,
+        Zone,
+    },
+    resource::{
+        EntityIndex,
+        PositionHistory,
+        SpatialGrid,
+        StaticGeometry,
+    },
+    EventQueue,
+};
+
+const DEFAULT_RADIUS: f64 = 0.5;
+const TARGET_MARGIN: f64 = 2.0;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CombatConfig {
+    pub max_health: u64,
+    pub attack_cooldown_ms: u64,
+    pub melee_range: f64,
+    pub melee_arc_degrees: f64,
+    pub melee_damage: u64,
+    pub hitscan_range: f64,
+    pub hitscan_damage: u64,
+    pub respawn_delay_ms: u64,
+    pub spawn_protection_ms: u64,
+}
+
+impl Default for CombatConfig {
+    fn default() -> CombatConfig {
+        CombatConfig {
+            max_health: 100,
+            attack_cooldown_ms: 500,
+            melee_range: 2.0,
+            melee_arc_degrees: 90.0,
+            melee_damage: 35,
+            hitscan_range: 100.0,
+            hitscan_damage: 20,
+            respawn_delay_ms: 5000,
+            spawn_protection_ms: 3000,
+        }
+    }
+}
+
+struct Target {
+    entity: Entity,
+    center: Point3<f64>,
+    radius: f64,
+}
+
+pub struct Combat {
+    config: CombatConfig,
+    view_radius: f64,
+    rewind_margin: f64,
+    sender: UnboundedSender<(Uuid, Outbound)>,
+}
+
+impl Combat {
+    /// `max_player_speed` bounds how fast a target can move, so that targets
+    /// are still found where they were up to `max_rewind` ago.
+    pub fn new(
+        config: CombatConfig,
+        view_radius: f64,
+        max_player_speed: f64,
+        max_rewind: Duration,
+        sender: UnboundedSender<(Uuid, Outbound)>,
+    ) -> Combat
+    {
+        Combat {
+            config,
+            view_radius,
+            rewind_margin: max_player_speed * max_rewind.as_secs_f64(),
+            sender,
+        }
+    }
+
+    fn melee(
+        &self,
+        geometry: &StaticGeometry,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+        targets: &[Target],
+    ) -> Option<Entity>
+    {
+        let min_alignment = (self.config.melee_arc_degrees.to_radians() / 2.0).cos();
+
+        targets.iter()
+            .filter_map(|target| {
+                let offset = target.center - origin;
+                let distance = offset.norm();
+                if distance - target.radius > self.config.melee_range {
+                    return None;
+                }
+                if distance > 0.0 && direction.dot(&(offset / distance)) < min_alignment {
+                    return None;
+                }
+                if geometry.raycast(origin, &target.center).is_some() {
+                    return None;
+                }
+                Some((distance, target.entity))
+            })
+            .min_by(|lhs, rhs| {
+                lhs.0.partial_cmp(&rhs.0).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(_, entity)| entity)
+    }
+
+    fn hitscan(
+        &self,
+        geometry: &StaticGeometry,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+        targets: &[Target],
+    ) -> Option<Entity>
+    {
+        targets.iter()
+            .filter_map(|target| {
+                let offset = target.center - origin;
+                let along = offset.dot(direction);
+                let miss_squared = offset.norm_squared() - along * along;
+                let radius_squared = target.radius * target.radius;
+                if along < 0.0 || miss_squared > radius_squared {
+                    return None;
+                }
+
+                let distance = (along - (radius_squared - miss_squared).sqrt()).max(0.0);
+                if distance > self.config.hitscan_range {
+                    return None;
+                }
+                if geometry.raycast(origin, &(origin + direction * distance)).is_some() {
+                    return None;
+                }
+                Some((distance, target.entity))
+            })
+            .min_by(|lhs, rhs| {
+                lhs.0.partial_cmp(&rhs.0).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(_, entity)| entity)
+    }
+
+    fn broadcast_death<'a>(
+        &self,
+        grid: &SpatialGrid,
+        ids: &ReadStorage<'a, Id>,
+        clients: &ReadStorage<'a, Client>,
+        center: &Point3<f64>,
+        victim: Uuid,
+        killer: Option<Entity>,
+    ) {
+        let mut recipients: HashSet<Entity> = grid.query(center, self.view_radius)
+            .into_iter()
+            .collect();
+        recipients.extend(killer);
+
+        let killer = killer.and_then(|killer| ids.get(killer)).map(|id| id.0);
+
+        for entity in recipients {
+            let connected = clients.get(entity)
+                .map(|client| matches!(client.state, ClientState::Connected))
+                == Some(true);
+            let uuid = match ids.get(entity) {
+                Some(id) if connected => id.0,
+                _ => continue,
+            };
+
+            let op = Operation::SvDeath(
+                operation::SvDeath { uuid: victim, killer }
+            );
+            self.sender.unbounded_send((uuid, Outbound::Send(Delivery::Reliable, op)))
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send death: {}", err);
+                });
+        }
+    }
+}
+
+impl<'a> System<'a> for Combat {
+    type SystemData = (
+        Read<'a, TickTime>,
+        Read<'a, EventQueue>,
+        ReadExpect<'a, SpatialGrid>,
+        ReadExpect<'a, StaticGeometry>,
+        ReadExpect<'a, PositionHistory>,
+        Read<'a, EntityIndex>,
+        ReadStorage<'a, Id>,
+        ReadStorage<'a, Client>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Latency>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, SpawnProtection>,
+        ReadStorage<'a, Zone>,
+        WriteStorage<'a, Attacker>,
+        WriteStorage<'a, Health>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            tick_time,
+            events,
+            grid,
+            geometry,
+            history,
+            index,
+            ids,
+            clients,
+            colliders,
+            latencies,
+            positions,
+            protection,
+            zones,
+            mut attackers,
+            mut health,
+        ) = data;
+
+        let cooldown = Duration::from_millis(self.config.attack_cooldown_ms);
+        let mut deaths = Vec::new();
+        let safe_zones: Vec<&Zone> = zones.join()
+            .filter(|zone| zone.is_safe())
+            .collect();
+
+        for event in &*events {
+            let attack = match event.op {
+                Operation::ClAttack(ref attack) => attack,
+                _ => continue,
+            };
+
+            let attacker = match index.get(&event.uuid) {
+                Some(entity) => entity,
+                None => continue,
+            };
+
+            if health.get(attacker).map(|health| health.0) == Some(0) {
+                continue;
+            }
+
+            let origin = match positions.get(attacker) {
+                Some(pos) => pos.0,
+                None => continue,
+            };
+
+            let length = attack.direction.norm();
+            if !length.is_finite() || length <= 0.0 {
+                log::debug!("Ignoring attack without direction from {}", event.uuid);
+                continue;
+            }
+            let direction = attack.direction / length;
+
+            if let Some(state) = attackers.get_mut(attacker) {
+                if state.ready_at > tick_time.0 {
+                    continue;
+                }
+                state.ready_at = tick_time.0 + cooldown;
+            }
+
+            let range = match attack.kind {
+                AttackKind::Melee => self.config.melee_range,
+                AttackKind::Hitscan => self.config.hitscan_range,
+            };
+            let rtt = latencies.get(attacker)
+                .map(|latency| latency.rtt)
+                .unwrap_or_default();
+            let seen_at = history.view_time(tick_time.0, rtt);
+
+            let reach = range + TARGET_MARGIN + self.rewind_margin;
+            let targets: Vec<Target> = grid.query(&origin, reach)
+                .into_iter()
+                .filter(|entity| *entity != attacker && !protection.contains(*entity))
+                .filter(|entity| health.get(*entity).map(|health| health.0 > 0) == Some(true))
+                .filter(|entity| {
+                    let pos = positions.get(*entity).map(|pos| pos.0);
+                    !safe_zones.iter().any(|zone| pos.map(|pos| zone.contains(&pos)) == Some(true))
+                })
+                .filter_map(|entity| {
+                    positions.get(entity).map(|pos| Target {
+                        entity,
+                        center: history.position_at(entity, seen_at).unwrap_or(pos.0),
+                        radius: colliders.get(entity)
+                            .map_or(DEFAULT_RADIUS, |collider| collider.radius),
+                    })
+                })
+                .collect();
+
+            let (target, damage) = match attack.kind {
+                AttackKind::Melee => (
+                    self.melee(&geometry, &origin, &direction, &targets),
+                    self.config.melee_damage,
+                ),
+                AttackKind::Hitscan => (
+                    self.hitscan(&geometry, &origin, &direction, &targets),
+                    self.config.hitscan_damage,
+                ),
+            };
+
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            if let Some(health) = health.get_mut(target) {
+                health.0 = health.0.saturating_sub(damage);
+                if health.0 == 0 {
+                    deaths.push((target, attacker, event.uuid));
+                }
+            }
+        }
+
+        for (victim, killer, killer_uuid) in deaths {
+            let (uuid, center) = match (ids.get(victim), positions.get(victim)) {
+                (Some(id), Some(pos)) => (id.0, pos.0),
+                _ => continue,
+            };
+
+            log::info!("Entity {} killed by {}", uuid, killer_uuid);
+
+            self.broadcast_death(&grid, &ids, &clients, &center, uuid, Some(killer));
+        }
+    }
+}
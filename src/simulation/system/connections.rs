@@ -1,31 +1,101 @@
+use std::collections::HashSet;
 use std::time::{
     Duration,
     Instant,
 };
 
+use futures::sync::mpsc::UnboundedSender;
 use specs::prelude::*;
+use uuid::Uuid;
 
 use eternalreckoning_core::net::operation::{
+    self,
     Operation,
 };
 use eternalreckoning_core::simulation::TickTime;
 
+use crate::auth::AuthWorker;
+use crate::networking::{
+    Delivery,
+    Outbound,
+};
+
 use super::super::{
     component::{
+        client::ClientState,
+        Account,
+        Attacker,
         Client,
+        Collider,
+        Health,
         Id,
+        Input,
+        Latency,
+        Name,
+        Orientation,
         Position,
+        Velocity,
+    },
+    resource::{
+        EntityIndex,
+        PlayerRecord,
+        PlayerStore,
+        SpawnPoints,
     },
     EventQueue,
 };
 
 pub struct Connections {
     ttl: Duration,
+    session_ttl: Duration,
+    player_radius: f64,
+    max_health: u64,
+    authenticator: AuthWorker,
+    authenticating: HashSet<Uuid>,
+    sender: UnboundedSender<(Uuid, Outbound)>,
 }
 
 impl Connections {
-    pub fn new(ttl: Duration) -> Connections {
-        Connections { ttl }
+    pub fn new(
+        ttl: Duration,
+        session_ttl: Duration,
+        player_radius: f64,
+        max_health: u64,
+        authenticator: AuthWorker,
+        sender: UnboundedSender<(Uuid, Outbound)>,
+    ) -> Connections
+    {
+        Connections {
+            ttl,
+            session_ttl,
+            player_radius,
+            max_health,
+            authenticator,
+            authenticating: HashSet::new(),
+            sender,
+        }
+    }
+
+    fn reject(&self, uuid: &Uuid, reason: &str) {
+        let op = Operation::DisconnectMessage(operation::DisconnectMessage {
+            reason: Some(reason.to_string()),
+        });
+        self.sender.unbounded_send((*uuid, Outbound::Send(Delivery::Reliable, op)))
+            .unwrap_or_else(|err| {
+                log::error!("Failed to send rejection: {}", err);
+            });
+        self.sender.unbounded_send((*uuid, Outbound::Remove))
+            .unwrap_or_else(|err| {
+                log::error!("Failed to remove connection to {}: {}", uuid, err);
+            });
+    }
+
+    fn refresh(&self, client: &mut Client, now: Instant) {
+        client.lifetime = now + self.ttl;
+
+        if let ClientState::Dropped = client.state {
+            client.state = ClientState::Connected;
+        }
     }
 }
 
@@ -34,9 +104,21 @@ impl<'a> System<'a> for Connections {
         Entities<'a>,
         Read<'a, TickTime>,
         Read<'a, EventQueue>,
+        WriteExpect<'a, SpawnPoints>,
+        Write<'a, EntityIndex>,
+        WriteExpect<'a, PlayerStore>,
+        WriteStorage<'a, Account>,
+        WriteStorage<'a, Attacker>,
         WriteStorage<'a, Client>,
+        WriteStorage<'a, Collider>,
+        WriteStorage<'a, Health>,
         WriteStorage<'a, Id>,
+        WriteStorage<'a, Input>,
+        WriteStorage<'a, Latency>,
+        WriteStorage<'a, Name>,
+        WriteStorage<'a, Orientation>,
         WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -44,90 +126,336 @@ impl<'a> System<'a> for Connections {
             entities,
             tick_time,
             events,
+            mut spawn_points,
+            mut index,
+            mut store,
+            mut accounts,
+            mut attackers,
             mut clients,
+            mut colliders,
+            mut health,
             mut ids,
+            mut inputs,
+            mut latencies,
+            mut names,
+            mut orientations,
             mut positions,
+            mut velocities,
         ) = data;
 
         for event in &*events {
             match event.op {
-                Operation::ClConnectMessage(_) => {
-                    log::info!("Client connected: {}", event.uuid);
-
-                    let client = entities.create();
-
-                    ids.insert(client, Id(event.uuid.clone()))
-                        .unwrap_or_else(|err| {
-                            log::error!(
-                                "Failed to add id for client {}: {}",
-                                event.uuid,
-                                err
-                            );
-                            None
-                        });
-
-                    clients.insert(client, Client::new(Instant::now() + self.ttl))
-                        .unwrap_or_else(|err| {
-                            log::error!(
-                                "Failed to add state for client {}: {}",
-                                event.uuid,
-                                err
-                            );
-                            None
-                        });
+                Operation::ClConnectMessage(ref connect) => {
+                    if let Some(entity) = index.get(&event.uuid) {
+                        if let Some(client) = clients.get_mut(entity) {
+                            log::info!("Client reconnected: {}", event.uuid);
+                            client.state = ClientState::Connecting;
+                            client.lifetime = tick_time.0 + self.ttl;
+                        }
+                        continue;
+                    }
 
-                    positions.insert(client, Position(
-                        nalgebra::Point3::<f64>::new(0.0, 0.0, 0.0)
-                    ))
-                        .unwrap_or_else(|err| {
-                            log::error!(
-                                "Failed to add position for client {}: {}",
-                                event.uuid,
-                                err
-                            );
-                            None
-                        });
+                    if !self.authenticating.insert(event.uuid) {
+                        continue;
+                    }
+                    let submitted = self.authenticator.submit(
+                        event.uuid,
+                        connect.credentials.clone()
+                    );
+                    match submitted {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            log::warn!("Rejected client {}: too many pending logins", event.uuid);
+                            self.authenticating.remove(&event.uuid);
+                            self.reject(&event.uuid, "Server busy");
+                        },
+                        Err(err) => {
+                            log::error!("Failed to authenticate client {}: {}", event.uuid, err);
+                            self.authenticating.remove(&event.uuid);
+                            self.reject(&event.uuid, "Authentication failed");
+                        },
+                    }
                 },
                 Operation::ClSync(_) => {
-                    for (id, client) in (&ids, &mut clients).join() {
-                        if id.0 == event.uuid {
-                            client.lifetime = tick_time.0 + self.ttl;
-                            break;
-                        }
+                    let client = index.get(&event.uuid)
+                        .and_then(|entity| clients.get_mut(entity));
+                    if let Some(client) = client {
+                        self.refresh(client, tick_time.0);
                     }
                 },
-                Operation::ClMoveSetPosition(_) => {
-                    for (id, client) in (&ids, &mut clients).join() {
-                        if id.0 == event.uuid {
-                            client.lifetime = tick_time.0 + self.ttl;
-                            break;
-                        }
+                Operation::ClInput(_)
+                | Operation::ClAttack(_)
+                | Operation::ClChat(_)
+                | Operation::ClPong(_) => {
+                    let client = index.get(&event.uuid)
+                        .and_then(|entity| clients.get_mut(entity));
+                    if let Some(client) = client {
+                        self.refresh(client, tick_time.0);
                     }
                 },
-                Operation::DisconnectMessage => {
-                    for (id, client) in (&ids, &mut clients).join() {
-                        if id.0 == event.uuid {
-                            client.lifetime = tick_time.0;
-                            break;
-                        }
+                Operation::DisconnectMessage(_) => {
+                    self.authenticating.remove(&event.uuid);
+
+                    let client = index.get(&event.uuid)
+                        .and_then(|entity| clients.get_mut(entity));
+                    if let Some(client) = client {
+                        client.state = ClientState::Dropped;
+                        client.lifetime = tick_time.0;
                     }
                 },
                 _ => (),
             }
         }
 
-        for (entity, id, client) in (&entities, &ids, &clients).join() {
-            if client.lifetime <= tick_time.0 {
-                log::info!("Client disconnected: {}", id.0);
-                entities.delete(entity)
+        while let Some((uuid, result)) = self.authenticator.try_recv() {
+            // The client left while its credentials were being checked.
+            if !self.authenticating.remove(&uuid) {
+                continue;
+            }
+
+            let identity = match result {
+                Ok(identity) => identity,
+                Err(err) => {
+                    log::warn!("Rejected client {}: {}", uuid, err);
+                    self.reject(&uuid, "Authentication failed");
+                    continue;
+                },
+            };
+
+            // Accounts identify players where the backend has them; names
+            // are all there is otherwise.
+            let existing = (&entities, &names, accounts.maybe(), &mut clients).join()
+                .find(|(_, name, account, _)| match identity.account {
+                    Some(ref wanted) => account.map(|account| &account.0) == Some(wanted),
+                    None => name.0 == identity.name,
+                });
+            if let Some((entity, _, _, existing)) = existing {
+                match existing.state {
+                    // Logged in again instead of reattaching. The old client
+                    // goes now, so its saved state passes to the new one.
+                    ClientState::Dropped => {
+                        existing.lifetime = tick_time.0;
+                        save(
+                            &mut store,
+                            accounts.get(entity),
+                            positions.get(entity),
+                            health.get(entity)
+                        );
+                    },
+                    _ => {
+                        log::warn!("Rejected client {}: {} is already connected", uuid, identity.name);
+                        self.reject(&uuid, "Already connected");
+                        continue;
+                    },
+                }
+            }
+
+            log::info!("Client connected: {} ({})", uuid, identity.name);
+
+            let client = entities.create();
+            index.insert(uuid, client);
+
+            ids.insert(client, Id(uuid))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add id for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            names.insert(client, Name(identity.name.clone()))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add name for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            clients.insert(client, Client::new(Instant::now() + self.ttl))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add state for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            let players: Vec<nalgebra::Point3<f64>> = (&clients, &positions).join()
+                .map(|(_, pos)| pos.0)
+                .collect();
+            let mut spawn = spawn_points.select(&players);
+            let mut initial_health = self.max_health;
+
+            if let Some(ref account) = identity.account {
+                // Players that left alive pick up where they were.
+                if let Some(record) = store.get(account) {
+                    if record.health > 0 {
+                        log::info!("Restoring saved state for {}", account);
+                        spawn.position = record.position;
+                        initial_health = record.health.min(self.max_health);
+                    }
+                }
+
+                accounts.insert(client, Account(account.clone()))
                     .unwrap_or_else(|err| {
                         log::error!(
-                            "Failed to drop disconnected client {}: {}",
-                            id.0,
+                            "Failed to add account for client {}: {}",
+                            uuid,
                             err
                         );
+                        None
                     });
             }
+
+            inputs.insert(client, Input::default())
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add input for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            positions.insert(client, Position(spawn.position))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add position for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            velocities.insert(client, Velocity(nalgebra::Vector3::zeros()))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add velocity for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            let orientation = nalgebra::UnitQuaternion::from_axis_angle(
+                &nalgebra::Vector3::y_axis(),
+                spawn.yaw
+            );
+            orientations.insert(client, Orientation(orientation))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add orientation for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            colliders.insert(client, Collider::new(self.player_radius))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add collider for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            health.insert(client, Health(initial_health))
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add health for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            attackers.insert(client, Attacker { ready_at: tick_time.0 })
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add attacker for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
+
+            latencies.insert(client, Latency::default())
+                .unwrap_or_else(|err| {
+                    log::error!(
+                        "Failed to add latency for client {}: {}",
+                        uuid,
+                        err
+                    );
+                    None
+                });
         }
+
+        let components = (
+            &entities,
+            &ids,
+            &mut clients,
+            accounts.maybe(),
+            positions.maybe(),
+            health.maybe(),
+        );
+
+        for (entity, id, client, account, position, health) in components.join() {
+            if client.lifetime > tick_time.0 {
+                continue;
+            }
+
+            match client.state {
+                ClientState::Dropped => {
+                    log::info!("Client disconnected: {}", id.0);
+
+                    save(&mut store, account, position, health);
+
+                    self.sender.unbounded_send((id.0, Outbound::Remove))
+                        .unwrap_or_else(|err| {
+                            log::error!("Failed to remove connection to {}: {}", id.0, err);
+                        });
+
+                    index.remove(&id.0);
+                    entities.delete(entity)
+                        .unwrap_or_else(|err| {
+                            log::error!(
+                                "Failed to drop disconnected client {}: {}",
+                                id.0,
+                                err
+                            );
+                        });
+                },
+                _ => {
+                    log::info!("Client timed out: {}", id.0);
+                    client.state = ClientState::Dropped;
+                    client.lifetime = tick_time.0 + self.session_ttl;
+                },
+            }
+        }
+    }
+}
+
+/// Saves the state of a player that is leaving. Only players with an
+/// authenticated account are saved.
+fn save(
+    store: &mut PlayerStore,
+    account: Option<&Account>,
+    position: Option<&Position>,
+    health: Option<&Health>,
+) {
+    if let (Some(account), Some(position), Some(health)) = (account, position, health) {
+        let record = PlayerRecord { position: position.0, health: health.0 };
+        store.save(&account.0, record)
+            .and_then(|_| store.flush())
+            .unwrap_or_else(|err| {
+                log::error!("Failed to save player {}: {}", account.0, err);
+            });
     }
 }
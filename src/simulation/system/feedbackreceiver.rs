@@ -0,0 +1,69 @@
+use std::sync::mpsc::Receiver;
+
+use specs::prelude::*;
+use uuid::Uuid;
+
+use eternalreckoning_core::simulation::TickTime;
+
+use crate::networking::Feedback;
+
+use super::super::{
+    component::{
+        Client,
+        Latency,
+        SnapshotHistory,
+    },
+    resource::EntityIndex,
+};
+
+pub struct FeedbackReceiver {
+    feedback: Receiver<(Uuid, Feedback)>,
+}
+
+impl FeedbackReceiver {
+    pub fn new(feedback: Receiver<(Uuid, Feedback)>) -> FeedbackReceiver {
+        FeedbackReceiver { feedback }
+    }
+}
+
+impl<'a> System<'a> for FeedbackReceiver {
+    type SystemData = (
+        Read<'a, TickTime>,
+        Read<'a, EntityIndex>,
+        WriteStorage<'a, Client>,
+        WriteStorage<'a, SnapshotHistory>,
+        WriteStorage<'a, Latency>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_time, index, mut clients, mut histories, mut latencies) = data;
+
+        for (uuid, feedback) in self.feedback.try_iter() {
+            let entity = match index.get(&uuid) {
+                Some(entity) => entity,
+                None => continue,
+            };
+
+            match feedback {
+                Feedback::Acknowledged(snapshot) => {
+                    if let Some(history) = histories.get_mut(entity) {
+                        history.acknowledge(snapshot);
+                    }
+                },
+                Feedback::Latency(stats) => {
+                    if let Some(latency) = latencies.get_mut(entity) {
+                        latency.rtt = stats.rtt;
+                        latency.jitter = stats.jitter;
+                        latency.clock_offset = stats.clock_offset;
+                    }
+                },
+                // Treated like a timeout, so the client can still reattach.
+                Feedback::Lost => {
+                    if let Some(client) = clients.get_mut(entity) {
+                        client.lifetime = tick_time.0;
+                    }
+                },
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,7 +1,31 @@
+mod chat;
+mod collision;
+mod combat;
 mod connections;
+mod feedbackreceiver;
+mod persistence;
+mod physics;
 mod playermovement;
+mod positionrecorder;
+mod respawn;
+mod spatialindexer;
 mod updatesender;
 
+pub use chat::{
+    Chat,
+    ChatConfig,
+};
+pub use collision::Collision;
+pub use combat::{
+    Combat,
+    CombatConfig,
+};
 pub use connections::Connections;
+pub use feedbackreceiver::FeedbackReceiver;
+pub use persistence::Persistence;
+pub use physics::Physics;
 pub use playermovement::PlayerMovement;
+pub use positionrecorder::PositionRecorder;
+pub use respawn::Respawn;
+pub use spatialindexer::SpatialIndexer;
 pub use updatesender::UpdateSender;
\ No newline at end of file
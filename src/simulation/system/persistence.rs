@@ -0,0 +1,82 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use specs::prelude::*;
+
+use eternalreckoning_core::simulation::TickTime;
+
+use super::super::{
+    component::{
+        Account,
+        Client,
+        Health,
+        Position,
+    },
+    resource::{
+        PlayerRecord,
+        PlayerStore,
+    },
+};
+
+type PersistenceData<'a> = (
+    WriteExpect<'a, PlayerStore>,
+    ReadStorage<'a, Client>,
+    ReadStorage<'a, Account>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Health>,
+);
+
+/// Periodically writes the state of every connected player to the
+/// `PlayerStore`.
+pub struct Persistence {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl Persistence {
+    pub fn new(interval: Duration) -> Persistence {
+        Persistence { interval, next: None }
+    }
+
+    /// Saves every player immediately, for use outside the dispatcher.
+    pub fn save_all(world: &World) {
+        save(world.system_data::<PersistenceData>());
+    }
+}
+
+fn save(data: PersistenceData) {
+    let (mut store, clients, accounts, positions, health) = data;
+
+    let mut saved = 0;
+    for (_, account, position, health) in (&clients, &accounts, &positions, &health).join() {
+        let record = PlayerRecord { position: position.0, health: health.0 };
+        match store.save(&account.0, record) {
+            Ok(()) => saved += 1,
+            Err(err) => log::error!("Failed to save player {}: {}", account.0, err),
+        }
+    }
+
+    match store.flush() {
+        Ok(()) => log::debug!("Saved {} players", saved),
+        Err(err) => log::error!("{}", err),
+    }
+}
+
+impl<'a> System<'a> for Persistence {
+    type SystemData = (
+        Read<'a, TickTime>,
+        PersistenceData<'a>,
+    );
+
+    fn run(&mut self, (tick_time, data): Self::SystemData) {
+        let next = *self.next.get_or_insert(tick_time.0 + self.interval);
+        if tick_time.0 < next {
+            return;
+        }
+        self.next = Some(tick_time.0 + self.interval);
+
+        save(data);
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use specs::prelude::*;
+
+use eternalreckoning_core::simulation::TickTime;
+
+use super::super::{
+    component::{
+        Acceleration,
+        Position,
+        Velocity,
+    },
+    resource::PhysicsConfig,
+};
+
+const MAX_STEP: Duration = Duration::from_millis(250);
+
+pub struct Physics {
+    previous: Option<Instant>,
+}
+
+impl Physics {
+    pub fn new() -> Physics {
+        Physics { previous: None }
+    }
+
+    fn step(&mut self, now: Instant) -> f64 {
+        let elapsed = match self.previous {
+            Some(previous) if now > previous => now - previous,
+            _ => Duration::from_millis(0),
+        };
+        self.previous = Some(now);
+
+        elapsed.min(MAX_STEP).as_secs_f64()
+    }
+}
+
+impl Default for Physics {
+    fn default() -> Physics {
+        Physics::new()
+    }
+}
+
+impl<'a> System<'a> for Physics {
+    type SystemData = (
+        Read<'a, TickTime>,
+        ReadExpect<'a, PhysicsConfig>,
+        ReadStorage<'a, Acceleration>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (tick_time, config, accelerations, mut velocities, mut positions) = data;
+
+        let dt = self.step(tick_time.0);
+        if dt <= 0.0 {
+            return;
+        }
+
+        let damping = (-config.drag * dt).exp();
+
+        for (acc, vel, pos) in (accelerations.maybe(), &mut velocities, &mut positions).join() {
+            let mut acceleration = config.gravity;
+            if let Some(acc) = acc {
+                acceleration += acc.0;
+            }
+
+            vel.0 += acceleration * dt;
+            vel.0 *= damping;
+            vel.0.y = vel.0.y.max(-config.max_fall_speed);
+            pos.0 += vel.0 * dt;
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,6 @@
+use std::collections::HashMap;
+
 use specs::prelude::*;
-use uuid::Uuid;
 
 use eternalreckoning_core::net::operation::{
     self,
@@ -8,43 +9,161 @@ use eternalreckoning_core::net::operation::{
 
 use super::super::{
     component::{
-        Id,
-        Position,
+        Collider,
+        Dead,
+        Input,
+        Orientation,
+        Velocity,
     },
+    resource::EntityIndex,
     EventQueue,
 };
 
-pub struct PlayerMovement;
+/// Commands held for a client that sends faster than the tick rate. Once
+/// full, the oldest command is dropped.
+const MAX_PENDING_COMMANDS: usize = 8;
+
+pub struct PlayerMovement {
+    max_speed: f64,
+    jump_speed: f64,
+}
+
+impl PlayerMovement {
+    pub fn new(max_speed: f64, jump_speed: f64) -> PlayerMovement {
+        PlayerMovement { max_speed, jump_speed }
+    }
+
+    /// Queues a command to be simulated on a later tick. Commands that are
+    /// out of order or repeated are ignored.
+    fn receive(input: &mut Input, command: &operation::ClInput) {
+        let newest = input.pending.back()
+            .map(|pending| pending.sequence)
+            .or(input.sequence);
+        if let Some(newest) = newest {
+            if command.sequence <= newest {
+                return;
+            }
+        }
+
+        if input.pending.len() >= MAX_PENDING_COMMANDS {
+            input.pending.pop_front();
+        }
+        input.pending.push_back(command.clone());
+    }
+
+    /// Simulates the next queued command for one tick. Without a new
+    /// command the player keeps moving and looking the same way, but
+    /// doesn't jump again.
+    fn apply(
+        &self,
+        input: &mut Input,
+        grounded: bool,
+        vel: &mut Velocity,
+        orientation: &mut Orientation,
+    ) {
+        let mut jump = false;
+        if let Some(command) = input.pending.pop_front() {
+            input.sequence = Some(command.sequence);
+            input.direction = command.direction;
+            input.yaw = command.yaw;
+            input.pitch = command.pitch;
+            jump = command.jump;
+        }
+
+        let direction = nalgebra::Vector3::new(input.direction.x, 0.0, input.direction.z);
+        let length = direction.norm();
+
+        let walk = if length.is_finite() && length > 0.0 {
+            direction * (self.max_speed / length.max(1.0))
+        } else {
+            nalgebra::Vector3::zeros()
+        };
+
+        vel.0.x = walk.x;
+        vel.0.z = walk.z;
+
+        if jump && grounded {
+            vel.0.y = self.jump_speed;
+        }
+
+        if input.yaw.is_finite() && input.pitch.is_finite() {
+            let yaw = nalgebra::UnitQuaternion::from_axis_angle(
+                &nalgebra::Vector3::y_axis(),
+                input.yaw
+            );
+            let pitch = nalgebra::UnitQuaternion::from_axis_angle(
+                &nalgebra::Vector3::x_axis(),
+                input.pitch
+            );
+            orientation.0 = yaw * pitch;
+        }
+    }
+}
 
 impl<'a> System<'a> for PlayerMovement {
     type SystemData = (
+        Entities<'a>,
         Read<'a, EventQueue>,
-        ReadStorage<'a, Id>,
-        WriteStorage<'a, Position>,
+        Read<'a, EntityIndex>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, Dead>,
+        WriteStorage<'a, Input>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Orientation>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (events, ids, mut pos) = data;
+        let (
+            entities,
+            events,
+            index,
+            colliders,
+            dead,
+            mut inputs,
+            mut velocities,
+            mut orientations,
+        ) = data;
 
-        let mut movement = Vec::<(&Uuid, &operation::ClMoveSetPosition)>::new();
+        let mut commands: HashMap<Entity, Vec<&operation::ClInput>> = HashMap::new();
 
         for event in &*events {
-            match event.op {
-                Operation::ClMoveSetPosition(ref data) => {
-                    movement.push((&event.uuid, data));
-                },
-                _ => (),
+            if let Operation::ClInput(ref data) = event.op {
+                if let Some(entity) = index.get(&event.uuid) {
+                    commands.entry(entity).or_default().push(data);
+                }
             }
         }
 
-        for (id, pos) in (&ids, &mut pos).join() {
-            for event in &movement {
-                if id.0 == *event.0 {
-                    pos.0.x = event.1.pos.x;
-                    pos.0.y = event.1.pos.y;
-                    pos.0.z = event.1.pos.z;
+        let components = (
+            &entities,
+            colliders.maybe(),
+            dead.maybe(),
+            &mut inputs,
+            &mut velocities,
+            &mut orientations,
+        );
+
+        for (entity, collider, dead, input, vel, orientation) in components.join() {
+            if let Some(commands) = commands.get(&entity) {
+                for command in commands {
+                    Self::receive(input, command);
+                }
+            }
+
+            if dead.is_some() {
+                // Commands are still used up one per tick, so the client
+                // keeps getting them acknowledged.
+                if let Some(command) = input.pending.pop_front() {
+                    input.sequence = Some(command.sequence);
                 }
+                input.direction = nalgebra::Vector3::zeros();
+                vel.0.x = 0.0;
+                vel.0.z = 0.0;
+                continue;
             }
+
+            let grounded = collider.map(|collider| collider.grounded) == Some(true);
+            self.apply(input, grounded, vel, orientation);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,33 @@
+use specs::prelude::*;
+
+use eternalreckoning_core::simulation::TickTime;
+
+use super::super::{
+    component::Position,
+    resource::{
+        PositionHistory,
+        Tick,
+    },
+};
+
+pub struct PositionRecorder;
+
+impl<'a> System<'a> for PositionRecorder {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, Tick>,
+        Read<'a, TickTime>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, PositionHistory>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, tick, tick_time, pos, mut history) = data;
+
+        history.record(
+            tick.0,
+            tick_time.0,
+            (&entities, &pos).join().map(|(entity, pos)| (entity, pos.0))
+        );
+    }
+}
\ No newline at end of file
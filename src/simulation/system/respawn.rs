@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use specs::prelude::*;
+
+use eternalreckoning_core::simulation::TickTime;
+
+use super::super::{
+    component::{
+        Client,
+        Collider,
+        Dead,
+        Health,
+        Id,
+        Orientation,
+        Position,
+        SpawnProtection,
+        Velocity,
+    },
+    resource::SpawnPoints,
+};
+
+pub struct Respawn {
+    delay: Duration,
+    protection: Duration,
+    max_health: u64,
+}
+
+impl Respawn {
+    pub fn new(delay: Duration, protection: Duration, max_health: u64) -> Respawn {
+        Respawn { delay, protection, max_health }
+    }
+}
+
+impl<'a> System<'a> for Respawn {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, TickTime>,
+        WriteExpect<'a, SpawnPoints>,
+        ReadStorage<'a, Id>,
+        ReadStorage<'a, Client>,
+        WriteStorage<'a, Dead>,
+        WriteStorage<'a, SpawnProtection>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Orientation>,
+        WriteStorage<'a, Collider>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            tick_time,
+            mut spawn_points,
+            ids,
+            clients,
+            mut dead,
+            mut protection,
+            mut health,
+            mut positions,
+            mut velocities,
+            mut orientations,
+            mut colliders,
+        ) = data;
+
+        let died: Vec<Entity> = (&entities, &health, !&dead).join()
+            .filter(|(_, health, _)| health.0 == 0)
+            .map(|(entity, _, _)| entity)
+            .collect();
+
+        for entity in died {
+            dead.insert(entity, Dead { until: tick_time.0 + self.delay })
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to mark entity as dead: {}", err);
+                    None
+                });
+            protection.remove(entity);
+        }
+
+        let respawning: Vec<Entity> = (&entities, &dead, &clients).join()
+            .filter(|(_, dead, _)| dead.until <= tick_time.0)
+            .map(|(entity, _, _)| entity)
+            .collect();
+
+        for entity in respawning {
+            let players: Vec<nalgebra::Point3<f64>> = (&clients, &positions, !&dead).join()
+                .map(|(_, pos, _)| pos.0)
+                .collect();
+            let spawn = spawn_points.select(&players);
+
+            dead.remove(entity);
+
+            if let Some(health) = health.get_mut(entity) {
+                health.0 = self.max_health;
+            }
+            if let Some(pos) = positions.get_mut(entity) {
+                pos.0 = spawn.position;
+            }
+            if let Some(vel) = velocities.get_mut(entity) {
+                vel.0 = nalgebra::Vector3::zeros();
+            }
+            if let Some(orientation) = orientations.get_mut(entity) {
+                orientation.0 = nalgebra::UnitQuaternion::from_axis_angle(
+                    &nalgebra::Vector3::y_axis(),
+                    spawn.yaw
+                );
+            }
+            if let Some(collider) = colliders.get_mut(entity) {
+                collider.previous = None;
+            }
+
+            protection.insert(entity, SpawnProtection { until: tick_time.0 + self.protection })
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to add spawn protection: {}", err);
+                    None
+                });
+
+            if let Some(id) = ids.get(entity) {
+                log::info!("Entity {} respawned", id.0);
+            }
+        }
+
+        let expired: Vec<Entity> = (&entities, &protection).join()
+            .filter(|(_, protection)| protection.until <= tick_time.0)
+            .map(|(entity, _)| entity)
+            .collect();
+
+        for entity in expired {
+            protection.remove(entity);
+        }
+    }
+}
\ No newline at end of file
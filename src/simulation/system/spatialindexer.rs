@@ -0,0 +1,26 @@
+use specs::prelude::*;
+
+use super::super::{
+    component::Position,
+    resource::SpatialGrid,
+};
+
+pub struct SpatialIndexer;
+
+impl<'a> System<'a> for SpatialIndexer {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, SpatialGrid>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, pos, mut grid) = data;
+
+        grid.clear();
+
+        for (entity, pos) in (&entities, &pos).join() {
+            grid.insert(entity, pos.0);
+        }
+    }
+}
\ No newline at end of file
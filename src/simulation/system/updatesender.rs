@@ -1,3 +1,9 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::time::Instant;
+
 use futures::sync::mpsc::UnboundedSender;
 use specs::prelude::*;
 use uuid::Uuid;
@@ -6,31 +12,79 @@ use eternalreckoning_core::net::operation::{
     self,
     Operation,
 };
+use eternalreckoning_core::simulation::TickTime;
+
+use crate::networking::{
+    Delivery,
+    Outbound,
+    SessionKey,
+};
 
-use super::super::component::{
-    client::ClientState,
-    Client,
-    Id,
-    Position,
-    Health,
+use super::super::{
+    component::{
+        client::ClientState,
+        snapshot::{
+            EntitySnapshot,
+            Snapshot,
+        },
+        Client,
+        Id,
+        Input,
+        Orientation,
+        Position,
+        Health,
+        SnapshotHistory,
+        Velocity,
+    },
+    resource::{
+        SpatialGrid,
+        Tick,
+    },
 };
 
 pub struct UpdateSender {
-    sender: UnboundedSender<(Uuid, Operation)>,
+    sender: UnboundedSender<(Uuid, Outbound)>,
+    sessions: SessionKey,
+    view_radius: f64,
+    refreshed_at: Instant,
 }
 
 impl UpdateSender {
-    pub fn new(sender: UnboundedSender<(Uuid, Operation)>) -> UpdateSender {
-        UpdateSender { sender }
+    pub fn new(
+        sender: UnboundedSender<(Uuid, Outbound)>,
+        sessions: SessionKey,
+        view_radius: f64,
+    ) -> UpdateSender
+    {
+        UpdateSender {
+            sender,
+            sessions,
+            view_radius,
+            refreshed_at: Instant::now(),
+        }
+    }
+
+    /// Sends the client a new session token. Tokens expire, so connected
+    /// clients are sent one every refresh interval.
+    fn send_token(&self, uuid: &Uuid) {
+        let op = Operation::SvConnectResponse(
+            operation::SvConnectResponse {
+                uuid: *uuid,
+                token: self.sessions.issue(uuid),
+            }
+        );
+
+        self.sender.unbounded_send((*uuid, Outbound::Send(Delivery::Reliable, op)))
+            .unwrap_or_else(|err| {
+                log::error!("Failed to send update: {}", err);
+            });
     }
 
     fn send_connection_response<'a>(
         &self,
-        _entities: &Entities<'a>,
         ids: &ReadStorage<'a, Id>,
-        _pos: &ReadStorage<'a, Position>,
-        _health: &ReadStorage<'a, Health>,
         clients: &mut WriteStorage<'a, Client>,
+        histories: &mut WriteStorage<'a, SnapshotHistory>,
         entity: Entity
     ) {
         let uuid = match ids.get(entity) {
@@ -40,28 +94,77 @@ impl UpdateSender {
                 return;
             }
         };
-        
-        let op = Operation::SvConnectResponse(
-            operation::SvConnectResponse { uuid: *uuid }
-        );
 
-        self.sender.unbounded_send((*uuid, op))
-            .unwrap_or_else(|err| {
-                log::error!("Failed to send update: {}", err);
-            });
-        
+        self.send_token(uuid);
+
         if let Some(client) = clients.get_mut(entity) {
             client.state = ClientState::Connected;
         }
+
+        histories.insert(entity, SnapshotHistory::new())
+            .unwrap_or_else(|err| {
+                log::error!(
+                    "Failed to add snapshot history for client {}: {}",
+                    uuid,
+                    err
+                );
+                None
+            });
+    }
+
+    fn build_snapshot(
+        &self,
+        captured: &HashMap<Entity, (Uuid, EntitySnapshot)>,
+        grid: &SpatialGrid,
+        entity: Entity,
+    ) -> Snapshot
+    {
+        let center = captured.get(&entity)
+            .and_then(|(_, snapshot)| snapshot.position);
+        let visible: Vec<Entity> = match center {
+            Some(center) => grid.query(&center, self.view_radius),
+            None => captured.keys().cloned().collect(),
+        };
+
+        visible.into_iter()
+            .filter_map(|ent| captured.get(&ent).cloned())
+            .collect()
+    }
+
+    fn send_visibility_changes(
+        &self,
+        uuid: &Uuid,
+        history: &mut SnapshotHistory,
+        snapshot: &Snapshot,
+    ) {
+        let visible: HashSet<Uuid> = snapshot.keys().cloned().collect();
+        let (entered, left) = history.update_visible(visible);
+
+        let left = left.into_iter()
+            .map(|id| Operation::SvEntityLeave(
+                operation::SvEntityLeave { uuid: id }
+            ));
+        let entered = entered.into_iter()
+            .filter(|id| id != uuid)
+            .map(|id| Operation::SvEntityEnter(
+                operation::SvEntityEnter { uuid: id }
+            ));
+
+        for op in left.chain(entered) {
+            self.sender.unbounded_send((*uuid, Outbound::Send(Delivery::Reliable, op)))
+                .unwrap_or_else(|err| {
+                    log::error!("Failed to send update: {}", err);
+                });
+        }
     }
 
     fn send_world_update<'a>(
         &self,
-        entities: &Entities<'a>,
         ids: &ReadStorage<'a, Id>,
-        pos: &ReadStorage<'a, Position>,
-        health: &ReadStorage<'a, Health>,
-        _clients: &mut WriteStorage<'a, Client>,
+        histories: &mut WriteStorage<'a, SnapshotHistory>,
+        tick: u64,
+        snapshot: Snapshot,
+        input_sequence: u32,
         entity: Entity
     ) {
         let uuid = match ids.get(entity) {
@@ -71,35 +174,24 @@ impl UpdateSender {
                 return;
             }
         };
-        let mut updates = Vec::new();
-
-        for (ent, id) in (entities, ids).join() {
-            let mut data = Vec::new();
-
-            if *uuid != id.0 {
-                if let Some(pos) = pos.get(ent) {
-                    data.push(operation::EntityComponent::Position(
-                        pos.0.clone()
-                    ));
-                }
+        let history = match histories.get_mut(entity) {
+            Some(history) => history,
+            None => {
+                log::error!("No snapshot history for client {}", uuid);
+                return;
             }
+        };
 
-            if let Some(health) = health.get(ent) {
-                data.push(operation::EntityComponent::Health(
-                    health.0
-                ));
-            }
+        self.send_visibility_changes(uuid, history, &snapshot);
 
-            updates.push(operation::EntityUpdate {
-                uuid: id.0,
-                data,
-            });
-        }
+        let updates = delta(history, &snapshot);
+
+        let snapshot_id = history.push(snapshot);
 
         let op = Operation::SvUpdateWorld(
-            operation::SvUpdateWorld { updates }
+            operation::SvUpdateWorld { tick, updates, input_sequence }
         );
-        self.sender.unbounded_send((*uuid, op))
+        self.sender.unbounded_send((*uuid, Outbound::Send(Delivery::Tracked(snapshot_id), op)))
             .unwrap_or_else(|err| {
                 log::error!("Failed to send update: {}", err);
             });
@@ -107,24 +199,150 @@ impl UpdateSender {
     }
 }
 
+/// Updates for every entity in `snapshot` that the client may not hold
+/// yet. Entities it has a baseline for are left out when nothing changed.
+fn delta(history: &SnapshotHistory, snapshot: &Snapshot) -> Vec<operation::EntityUpdate> {
+    let mut updates = Vec::new();
+
+    for (id, current) in snapshot {
+        let previous = history.baseline()
+            .and_then(|baseline| baseline.get(id));
+        let sent: Vec<&EntitySnapshot> = history.unacknowledged()
+            .filter_map(|sent| sent.get(id))
+            .collect();
+        let mut data = Vec::new();
+
+        if let Some(pos) = current.position {
+            if changed(&pos, previous, &sent, |snapshot| snapshot.position) {
+                data.push(operation::EntityComponent::Position(pos));
+            }
+        }
+
+        if let Some(health) = current.health {
+            if changed(&health, previous, &sent, |snapshot| snapshot.health) {
+                data.push(operation::EntityComponent::Health(health));
+            }
+        }
+
+        if let Some(vel) = current.velocity {
+            if changed(&vel, previous, &sent, |snapshot| snapshot.velocity) {
+                data.push(operation::EntityComponent::Velocity(vel));
+            }
+        }
+
+        if let Some(orientation) = current.orientation {
+            if changed(&orientation, previous, &sent, |snapshot| snapshot.orientation) {
+                data.push(operation::EntityComponent::Orientation(orientation));
+            }
+        }
+
+        if previous.is_some() && data.is_empty() {
+            continue;
+        }
+
+        updates.push(operation::EntityUpdate {
+            uuid: *id,
+            data,
+        });
+    }
+
+    updates
+}
+
+/// Whether a value has to be sent. The client may hold the acknowledged
+/// baseline or anything sent since, so a value is only left out when all of
+/// them agree on it. After a change A→B→A the client could still hold B.
+fn changed<T, F>(
+    value: &T,
+    baseline: Option<&EntitySnapshot>,
+    sent: &[&EntitySnapshot],
+    field: F,
+) -> bool
+where
+    T: PartialEq,
+    F: Fn(&EntitySnapshot) -> Option<T>,
+{
+    baseline.and_then(&field).as_ref() != Some(value)
+        || sent.iter().any(|snapshot| field(snapshot).as_ref() != Some(value))
+}
+
+fn capture<'a>(
+    entities: &Entities<'a>,
+    ids: &ReadStorage<'a, Id>,
+    pos: &ReadStorage<'a, Position>,
+    health: &ReadStorage<'a, Health>,
+    velocities: &ReadStorage<'a, Velocity>,
+    orientations: &ReadStorage<'a, Orientation>,
+) -> HashMap<Entity, (Uuid, EntitySnapshot)>
+{
+    let components = (
+        entities,
+        ids,
+        pos.maybe(),
+        health.maybe(),
+        velocities.maybe(),
+        orientations.maybe(),
+    );
+
+    components.join()
+        .map(|(entity, id, pos, health, vel, orientation)| {
+            (entity, (id.0, EntitySnapshot {
+                position: pos.map(|pos| pos.0),
+                health: health.map(|health| health.0),
+                velocity: vel.map(|vel| vel.0),
+                orientation: orientation.map(|orientation| orientation.0),
+            }))
+        })
+        .collect()
+}
+
 impl<'a> System<'a> for UpdateSender {
     type SystemData = (
         Entities<'a>,
+        Read<'a, Tick>,
+        Read<'a, TickTime>,
         ReadStorage<'a, Id>,
+        ReadStorage<'a, Input>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Health>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Orientation>,
+        ReadExpect<'a, SpatialGrid>,
         WriteStorage<'a, Client>,
+        WriteStorage<'a, SnapshotHistory>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
+            tick,
+            tick_time,
             ids,
+            inputs,
             pos,
             health,
+            velocities,
+            orientations,
+            grid,
             mut clients,
+            mut histories,
         ) = data;
 
+        let captured = capture(
+            &entities,
+            &ids,
+            &pos,
+            &health,
+            &velocities,
+            &orientations
+        );
+
+        let refresh = tick_time.0.saturating_duration_since(self.refreshed_at)
+            >= self.sessions.refresh_interval();
+        if refresh {
+            self.refreshed_at = tick_time.0;
+        }
+
         for ent in entities.join() {
             let state = {
                 match clients.get(ent) {
@@ -136,25 +354,109 @@ impl<'a> System<'a> for UpdateSender {
             match state {
                 ClientState::Connecting => {
                     self.send_connection_response(
-                        &entities,
                         &ids,
-                        &pos,
-                        &health,
                         &mut clients,
+                        &mut histories,
                         ent
                     );
                 },
                 ClientState::Connected => {
+                    if refresh {
+                        if let Some(id) = ids.get(ent) {
+                            self.send_token(&id.0);
+                        }
+                    }
+
+                    let snapshot = self.build_snapshot(&captured, &grid, ent);
+                    let input_sequence = inputs.get(ent)
+                        .map_or(0, Input::acknowledged);
                     self.send_world_update(
-                        &entities,
                         &ids,
-                        &pos,
-                        &health,
-                        &mut clients,
+                        &mut histories,
+                        tick.0,
+                        snapshot,
+                        input_sequence,
                         ent
                     );
                 },
+                ClientState::Dropped => (),
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(uuid: Uuid, health: u64) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert(uuid, EntitySnapshot {
+            position: None,
+            health: Some(health),
+            velocity: None,
+            orientation: None,
+        });
+        snapshot
+    }
+
+    /// The health sent for `uuid`, or None when the entity was left out.
+    fn sent_health(history: &SnapshotHistory, current: &Snapshot, uuid: Uuid)
+        -> Option<Option<u64>>
+    {
+        delta(history, current).into_iter()
+            .find(|update| update.uuid == uuid)
+            .map(|update| update.data.iter().find_map(|component| match component {
+                operation::EntityComponent::Health(health) => Some(*health),
+                _ => None,
+            }))
+    }
+
+    #[test]
+    fn omits_values_the_client_holds_in_every_copy() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        assert_eq!(sent_health(&history, &snapshot(uuid, 10), uuid), Some(Some(10)));
+
+        let id = history.push(snapshot(uuid, 10));
+        history.acknowledge(id);
+        history.push(snapshot(uuid, 10));
+
+        assert_eq!(sent_health(&history, &snapshot(uuid, 10), uuid), None);
+        assert_eq!(sent_health(&history, &snapshot(uuid, 5), uuid), Some(Some(5)));
+    }
+
+    #[test]
+    fn resends_values_until_they_are_acknowledged() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+        let id = history.push(snapshot(uuid, 10));
+        history.acknowledge(id);
+
+        // The snapshot carrying 5 may be lost, so 5 goes out again.
+        history.push(snapshot(uuid, 5));
+        assert_eq!(sent_health(&history, &snapshot(uuid, 5), uuid), Some(Some(5)));
+
+        // After 10 → 5 → 10 the client may still hold 5.
+        assert_eq!(sent_health(&history, &snapshot(uuid, 10), uuid), Some(Some(10)));
+
+        let id = history.push(snapshot(uuid, 5));
+        history.acknowledge(id);
+        assert_eq!(sent_health(&history, &snapshot(uuid, 5), uuid), None);
+    }
+
+    #[test]
+    fn sends_full_state_while_nothing_is_acknowledged() {
+        let uuid = Uuid::new_v4();
+        let mut history = SnapshotHistory::new();
+
+        let evicted = history.push(snapshot(uuid, 10));
+        for _ in 0..100 {
+            history.push(snapshot(uuid, 10));
+        }
+        history.acknowledge(evicted);
+
+        assert!(history.baseline().is_none());
+        assert_eq!(sent_health(&history, &snapshot(uuid, 10), uuid), Some(Some(10)));
+    }
+}
@@ -0,0 +1,292 @@
+use std::env;
+
+use failure::{
+    format_err,
+    Error,
+};
+
+use crate::server::BindAddress;
+use crate::util::config::Config;
+
+const ENV_PREFIX: &str = "ER_SERVER_";
+const OPTIONS: &[&str] = &["config", "bind", "tick-rate", "log-level"];
+
+pub const USAGE: &str = "\
+Usage: eternalreckoning-server [options]
+
+Options:
+    --config <path>           Configuration file to load
+    --bind <address>          Address to listen on, may be repeated
+    --tick-rate <rate>        Simulation ticks per second
+    --log-level <level>       Log level
+    --print-default-config    Print the default configuration and exit
+    --check-config            Validate the configuration and exit
+    --help                    Print this message and exit
+
+Every option that takes a value can also be set through an environment
+variable, such as ER_SERVER_TICK_RATE for --tick-rate. Multiple bind
+addresses are separated by commas. Options take precedence over the
+environment, which takes precedence over the configuration file.";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Run,
+    CheckConfig,
+    PrintDefaultConfig,
+    Help,
+}
+
+/// Settings that can be given on the command line or in the environment,
+/// on top of the configuration file.
+#[derive(Default)]
+pub struct Overrides {
+    pub config: Option<String>,
+    pub bind: Option<Vec<String>>,
+    pub tick_rate: Option<u64>,
+    pub log_level: Option<log::LevelFilter>,
+}
+
+impl Overrides {
+    pub fn from_env() -> Result<Overrides, Error> {
+        Overrides::from_vars(|var| env::var(var).ok())
+    }
+
+    fn from_vars<F>(lookup: F) -> Result<Overrides, Error>
+        where F: Fn(&str) -> Option<String>
+    {
+        let mut overrides = Overrides::default();
+
+        for name in OPTIONS {
+            let var = format!(
+                "{}{}",
+                ENV_PREFIX,
+                name.replace('-', "_").to_uppercase()
+            );
+            match lookup(&var) {
+                Some(ref value) if !value.is_empty() => {
+                    overrides.set(name, value)
+                        .map_err(|err| format_err!("Invalid {}: {}", var, err))?;
+                },
+                _ => (),
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        match name {
+            "config" => self.config = Some(value.to_string()),
+            "bind" => {
+                self.bind.get_or_insert_with(Vec::new).extend(
+                    value.split(',')
+                        .map(|address| address.trim())
+                        .filter(|address| !address.is_empty())
+                        .map(|address| address.to_string())
+                );
+            },
+            "tick-rate" => {
+                let tick_rate = value.parse::<u64>()
+                    .map_err(|err| format_err!("{} ({})", value, err))?;
+                self.tick_rate = Some(tick_rate);
+            },
+            "log-level" => {
+                let log_level = value.parse::<log::LevelFilter>()
+                    .map_err(|err| format_err!("{} ({})", value, err))?;
+                self.log_level = Some(log_level);
+            },
+            _ => unreachable!("not an option: {}", name),
+        }
+        Ok(())
+    }
+
+    /// Takes every setting that is present in `other`.
+    pub fn merge(&mut self, other: Overrides) {
+        if other.config.is_some() {
+            self.config = other.config;
+        }
+        if other.bind.is_some() {
+            self.bind = other.bind;
+        }
+        if other.tick_rate.is_some() {
+            self.tick_rate = other.tick_rate;
+        }
+        if other.log_level.is_some() {
+            self.log_level = other.log_level;
+        }
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(ref bind) = self.bind {
+            config.server.bind_address = match bind.as_slice() {
+                [address] => BindAddress::One(address.clone()),
+                addresses => BindAddress::Many(addresses.to_vec()),
+            };
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.server.tick_rate = tick_rate;
+        }
+        if let Some(level) = self.log_level {
+            config.logging.level = level.to_string().to_lowercase();
+        }
+    }
+}
+
+pub struct Options {
+    pub mode: Mode,
+    pub overrides: Overrides,
+}
+
+impl Options {
+    /// Parses the arguments following the program name. Values may be given
+    /// either as `--option value` or `--option=value`.
+    pub fn parse(args: &[String]) -> Result<Options, Error> {
+        let mut mode = Mode::Run;
+        let mut overrides = Overrides::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let option = arg.strip_prefix("--")
+                .ok_or_else(|| format_err!("Unexpected argument: {}", arg))?;
+
+            let (name, inline) = match option.find('=') {
+                Some(index) => (&option[..index], Some(&option[index + 1..])),
+                None => (option, None),
+            };
+
+            let flag = match name {
+                "check-config" => Some(Mode::CheckConfig),
+                "print-default-config" => Some(Mode::PrintDefaultConfig),
+                "help" => Some(Mode::Help),
+                _ => None,
+            };
+            if let Some(flag) = flag {
+                if inline.is_some() {
+                    return Err(format_err!("Option --{} does not take a value", name));
+                }
+                if mode != Mode::Run && mode != flag {
+                    return Err(format_err!("Option --{} conflicts with another mode", name));
+                }
+                mode = flag;
+                continue;
+            }
+
+            if !OPTIONS.contains(&name) {
+                return Err(format_err!("Unknown option --{}", name));
+            }
+
+            let value = match inline {
+                Some(value) => value,
+                None => args.next()
+                    .ok_or_else(|| format_err!("Option --{} requires a value", name))?,
+            };
+            overrides.set(name, value)
+                .map_err(|err| format_err!("Invalid --{}: {}", name, err))?;
+        }
+
+        Ok(Options { mode, overrides })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Result<Overrides, Error> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Overrides::from_vars(|var| vars.get(var).cloned())
+    }
+
+    /// Layers the settings the way `run` does: defaults, then the file, then
+    /// the environment, then the command line.
+    fn resolve(file: &str, env: Overrides, flags: &[&str]) -> Config {
+        let mut overrides = env;
+        overrides.merge(Options::parse(&args(flags)).unwrap().overrides);
+
+        let mut config: Config = toml::from_str(file).unwrap();
+        overrides.apply(&mut config);
+        config
+    }
+
+    #[test]
+    fn layers_defaults_file_environment_and_flags() {
+        let file = "[server]\ntick-rate = 30\nbind-address = \"0.0.0.0:7000\"\n";
+
+        let config = resolve("", Overrides::default(), &[]);
+        assert_eq!(config.server.tick_rate, 60);
+
+        let config = resolve(file, Overrides::default(), &[]);
+        assert_eq!(config.server.tick_rate, 30);
+        assert_eq!(config.server.bind_address.addresses(), vec!["0.0.0.0:7000"]);
+
+        let env = vars(&[("ER_SERVER_TICK_RATE", "20"), ("ER_SERVER_LOG_LEVEL", "debug")]).unwrap();
+        let config = resolve(file, env, &[]);
+        assert_eq!(config.server.tick_rate, 20);
+        assert_eq!(config.server.bind_address.addresses(), vec!["0.0.0.0:7000"]);
+        assert_eq!(config.logging.level, "debug");
+
+        let env = vars(&[("ER_SERVER_TICK_RATE", "20"), ("ER_SERVER_LOG_LEVEL", "debug")]).unwrap();
+        let config = resolve(file, env, &["--tick-rate=10", "--bind", "[::]:7000"]);
+        assert_eq!(config.server.tick_rate, 10);
+        assert_eq!(config.server.bind_address.addresses(), vec!["[::]:7000"]);
+        assert_eq!(config.logging.level, "debug");
+
+        let env = vars(&[("ER_SERVER_LOG_LEVEL", "debug")]).unwrap();
+        let config = resolve(file, env, &["--log-level=WARN"]);
+        assert_eq!(config.logging.level, "warn");
+    }
+
+    #[test]
+    fn collects_bind_addresses() {
+        let env = vars(&[("ER_SERVER_BIND", "0.0.0.0:7000, [::]:7000,")]).unwrap();
+        assert_eq!(env.bind.unwrap(), vec!["0.0.0.0:7000", "[::]:7000"]);
+
+        let options = Options::parse(&args(&["--bind", "a", "--bind=b"])).unwrap();
+        assert_eq!(options.overrides.bind.unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn ignores_empty_environment_variables() {
+        let env = vars(&[("ER_SERVER_TICK_RATE", ""), ("ER_SERVER_CONFIG", "")]).unwrap();
+
+        assert!(env.tick_rate.is_none());
+        assert!(env.config.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(vars(&[("ER_SERVER_TICK_RATE", "fast")]).is_err());
+        assert!(vars(&[("ER_SERVER_LOG_LEVEL", "loud")]).is_err());
+
+        for invalid in [
+            &["--tick-rate"][..],
+            &["--tick-rate", "-1"],
+            &["--log-level=loud"],
+            &["--unknown", "1"],
+            &["config.toml"],
+            &["--help=yes"],
+            &["--help", "--check-config"],
+        ].iter() {
+            assert!(Options::parse(&args(invalid)).is_err(), "accepted {:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn selects_modes() {
+        let mode = |flags: &[&str]| Options::parse(&args(flags)).unwrap().mode;
+
+        assert!(mode(&[]) == Mode::Run);
+        assert!(mode(&["--check-config"]) == Mode::CheckConfig);
+        assert!(mode(&["--check-config", "--check-config"]) == Mode::CheckConfig);
+        assert!(mode(&["--print-default-config"]) == Mode::PrintDefaultConfig);
+        assert!(mode(&["--help"]) == Mode::Help);
+    }
+}
@@ -1 +1,2 @@
-pub mod config;
\ No newline at end of file
+pub mod cli;
+pub mod config;